@@ -0,0 +1,115 @@
+use crate::ray::Ray;
+use crate::vector::Vec3;
+
+// Axis-aligned bounding box
+#[derive(Debug, Copy, Clone)]
+pub struct Aabb {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl Aabb {
+    pub fn new(min: Vec3, max: Vec3) -> Aabb {
+        Aabb { min, max }
+    }
+
+    // Inverted box that any union replaces
+    pub fn empty() -> Aabb {
+        Aabb {
+            min: Vec3 {
+                x: f64::INFINITY,
+                y: f64::INFINITY,
+                z: f64::INFINITY,
+            },
+            max: Vec3 {
+                x: f64::NEG_INFINITY,
+                y: f64::NEG_INFINITY,
+                z: f64::NEG_INFINITY,
+            },
+        }
+    }
+
+    pub fn from_points(points: &[Vec3]) -> Aabb {
+        points
+            .iter()
+            .fold(Aabb::empty(), |bounds, point| bounds.grow(point))
+    }
+
+    pub fn grow(&self, point: &Vec3) -> Aabb {
+        Aabb {
+            min: Vec3 {
+                x: self.min.x.min(point.x),
+                y: self.min.y.min(point.y),
+                z: self.min.z.min(point.z),
+            },
+            max: Vec3 {
+                x: self.max.x.max(point.x),
+                y: self.max.y.max(point.y),
+                z: self.max.z.max(point.z),
+            },
+        }
+    }
+
+    pub fn union(&self, other: &Aabb) -> Aabb {
+        self.grow(&other.min).grow(&other.max)
+    }
+
+    pub fn center(&self) -> Vec3 {
+        (self.min + self.max) / 2.0
+    }
+
+    // Box around the eight corners after moving them
+    pub fn transformed(&self, f: impl Fn(&Vec3) -> Vec3) -> Aabb {
+        let mut transformed: Aabb = Aabb::empty();
+        for i in 0..8 {
+            let corner: Vec3 = Vec3 {
+                x: if i & 1 == 0 { self.min.x } else { self.max.x },
+                y: if i & 2 == 0 { self.min.y } else { self.max.y },
+                z: if i & 4 == 0 { self.min.z } else { self.max.z },
+            };
+            transformed = transformed.grow(&f(&corner));
+        }
+        transformed
+    }
+
+    // Zero for empty or inverted boxes
+    pub fn surface_area(&self) -> f64 {
+        let size: Vec3 = self.max - self.min;
+        if size.x < 0.0 || size.y < 0.0 || size.z < 0.0 {
+            return 0.0;
+        }
+        2.0 * (size.x * size.y + size.y * size.z + size.z * size.x)
+    }
+
+    // Distance along the ray to where it enters the box (0 if it starts inside),
+    // if that is closer than t_max
+    pub fn hit(&self, r: &Ray, t_max: f64) -> Option<f64> {
+        self.interval(r, t_max).map(|(t_near, _)| t_near)
+    }
+
+    // Distances along the ray to where it enters and leaves the box, clipped to
+    // 0 and t_max
+    pub fn interval(&self, r: &Ray, t_max: f64) -> Option<(f64, f64)> {
+        let origin = [r.origin().x, r.origin().y, r.origin().z];
+        let direction = [r.direction().x, r.direction().y, r.direction().z];
+        let min = [self.min.x, self.min.y, self.min.z];
+        let max = [self.max.x, self.max.y, self.max.z];
+
+        let mut t_near: f64 = 0.0;
+        let mut t_far: f64 = t_max;
+        for axis in 0..3 {
+            let inv_dir: f64 = 1.0 / direction[axis];
+            let mut t0: f64 = (min[axis] - origin[axis]) * inv_dir;
+            let mut t1: f64 = (max[axis] - origin[axis]) * inv_dir;
+            if t0 > t1 {
+                std::mem::swap(&mut t0, &mut t1);
+            }
+            t_near = t_near.max(t0);
+            t_far = t_far.min(t1);
+            if t_near > t_far {
+                return None;
+            }
+        }
+        Some((t_near, t_far))
+    }
+}
@@ -0,0 +1,538 @@
+use crate::aabb::Aabb;
+use crate::instance::Instance;
+use crate::mesh::Mesh;
+use crate::object::{Hit, Object, ObjectTrait};
+use crate::ray::Ray;
+use crate::vector::Vec3;
+
+const BINS: usize = 12;
+const MAX_LEAF_SIZE: usize = 4;
+const TRAVERSAL_COST: f64 = 1.0; // relative to testing one primitive
+const REBUILD_THRESHOLD: f64 = 1.5; // rebuild once refitting has made rays this much slower
+
+// What a leaf of the tree points at
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Primitive {
+    Object(usize),
+    Instance(usize),
+    Triangle { mesh: usize, index: usize },
+    Point { mesh: usize, index: usize }, // vertex of a point cloud
+}
+
+#[derive(Debug, Copy, Clone)]
+struct Node {
+    bounds: Aabb,
+    // Leaves hold `count` primitives starting at `first`. Inner nodes have a count
+    // of 0 and their two children at `first` and `first + 1`.
+    first: usize,
+    count: usize,
+}
+
+// Bounding volume hierarchy over the objects, instances and mesh faces of a scene
+#[derive(Debug, Clone)]
+pub struct Bvh {
+    nodes: Vec<Node>,
+    primitives: Vec<Primitive>,
+    unbounded: Vec<Primitive>, // ones with no bounds, like infinite planes, tested every time
+    layout: Vec<usize>,        // object, instance and per-mesh primitive counts, to spot changes
+    built_cost: f64,
+}
+
+impl Bvh {
+    // Builds the tree top down, splitting each node where the surface area heuristic
+    // says a ray will do the least work
+    pub fn build(objects: &[Object], meshes: &[Mesh], instances: &[Instance]) -> Bvh {
+        let mut unbounded: Vec<Primitive> = Vec::new();
+        let mut items: Vec<(Primitive, Aabb)> = Vec::new();
+        let whole = (0..objects.len())
+            .map(Primitive::Object)
+            .chain((0..instances.len()).map(Primitive::Instance));
+        for primitive in whole {
+            match primitive_bounds(&primitive, objects, meshes, instances) {
+                Some(bounds) => items.push((primitive, bounds)),
+                None => unbounded.push(primitive),
+            }
+        }
+        for (mesh_index, mesh) in meshes.iter().enumerate() {
+            for index in 0..mesh_primitive_count(mesh) {
+                let primitive: Primitive = if mesh.triangle_count() > 0 {
+                    Primitive::Triangle {
+                        mesh: mesh_index,
+                        index,
+                    }
+                } else {
+                    Primitive::Point {
+                        mesh: mesh_index,
+                        index,
+                    }
+                };
+                if let Some(bounds) = primitive_bounds(&primitive, objects, meshes, instances) {
+                    items.push((primitive, bounds));
+                }
+            }
+        }
+        let layout: Vec<usize> = layout(objects, meshes, instances);
+
+        let mut nodes: Vec<Node> = vec![Node {
+            bounds: Aabb::empty(),
+            first: 0,
+            count: 0,
+        }];
+        // Explicit work list rather than recursion, as lopsided trees can get deep
+        let mut work: Vec<(usize, usize, usize)> = vec![(0, 0, items.len())];
+        while let Some((node, start, end)) = work.pop() {
+            let range: &mut [(Primitive, Aabb)] = &mut items[start..end];
+            let bounds: Aabb = range
+                .iter()
+                .fold(Aabb::empty(), |bounds, (_, item)| bounds.union(item));
+            nodes[node].bounds = bounds;
+
+            match split(range, &bounds) {
+                Some(middle) => {
+                    let left: usize = nodes.len();
+                    for _ in 0..2 {
+                        nodes.push(Node {
+                            bounds: Aabb::empty(),
+                            first: 0,
+                            count: 0,
+                        });
+                    }
+                    nodes[node].first = left;
+                    work.push((left, start, start + middle));
+                    work.push((left + 1, start + middle, end));
+                }
+                None => {
+                    nodes[node].first = start;
+                    nodes[node].count = end - start;
+                }
+            }
+        }
+
+        let mut bvh = Bvh {
+            nodes,
+            primitives: items.into_iter().map(|(primitive, _)| primitive).collect(),
+            unbounded,
+            layout,
+            built_cost: 0.0,
+        };
+        bvh.built_cost = bvh.cost();
+        bvh
+    }
+
+    // Recomputes every node's bounds bottom up after primitives have moved, keeping
+    // the shape of the tree. Returns false if objects or faces were added or removed,
+    // in which case the tree has to be rebuilt instead.
+    pub fn refit(&mut self, objects: &[Object], meshes: &[Mesh], instances: &[Instance]) -> bool {
+        if self.layout != layout(objects, meshes, instances) {
+            return false;
+        }
+        // Children are always stored after their parent, so walking backwards
+        // visits them first
+        for index in (0..self.nodes.len()).rev() {
+            let node: Node = self.nodes[index];
+            let bounds: Aabb = if node.count > 0 {
+                let mut bounds: Aabb = Aabb::empty();
+                for primitive in &self.primitives[node.first..node.first + node.count] {
+                    match primitive_bounds(primitive, objects, meshes, instances) {
+                        Some(primitive) => bounds = bounds.union(&primitive),
+                        None => return false,
+                    }
+                }
+                bounds
+            } else if self.primitives.is_empty() {
+                Aabb::empty()
+            } else {
+                self.nodes[node.first]
+                    .bounds
+                    .union(&self.nodes[node.first + 1].bounds)
+            };
+            self.nodes[index].bounds = bounds;
+        }
+        true
+    }
+
+    // Expected work for a ray through the tree by the surface area heuristic: each
+    // node costs what testing it does, weighted by the chance a ray that hits the
+    // root hits it too
+    pub fn cost(&self) -> f64 {
+        let root: f64 = self.nodes[0].bounds.surface_area();
+        if root <= 0.0 {
+            return 0.0;
+        }
+        self.nodes
+            .iter()
+            .map(|node| {
+                let work: f64 = if node.count > 0 {
+                    node.count as f64
+                } else {
+                    TRAVERSAL_COST
+                };
+                node.bounds.surface_area() / root * work
+            })
+            .sum()
+    }
+
+    // Whether refitting has stretched the boxes enough that a rebuild will pay off
+    pub fn degraded(&self) -> bool {
+        self.cost() > self.built_cost * REBUILD_THRESHOLD
+    }
+
+    // Closest hit, visiting the nearer child first so farther ones can often be skipped
+    pub fn intersect(
+        &self,
+        objects: &[Object],
+        meshes: &[Mesh],
+        instances: &[Instance],
+        r: &Ray,
+    ) -> Option<Hit> {
+        let mut closest: Option<Hit> = None;
+        for primitive in &self.unbounded {
+            if let Some(hit) = intersect_primitive(primitive, objects, meshes, instances, r) {
+                if closest.is_none_or(|c| hit.t < c.t) {
+                    closest = Some(hit);
+                }
+            }
+        }
+        if self.primitives.is_empty() {
+            return closest;
+        }
+
+        let t_max = |closest: &Option<Hit>| closest.map_or(f64::INFINITY, |hit| hit.t);
+        let mut stack: Vec<(usize, f64)> = Vec::with_capacity(64);
+        if let Some(t) = self.nodes[0].bounds.hit(r, t_max(&closest)) {
+            stack.push((0, t));
+        }
+        while let Some((index, t_enter)) = stack.pop() {
+            if t_enter > t_max(&closest) {
+                continue;
+            }
+            let node: &Node = &self.nodes[index];
+            if node.count > 0 {
+                for primitive in &self.primitives[node.first..node.first + node.count] {
+                    if let Some(hit) = intersect_primitive(primitive, objects, meshes, instances, r)
+                    {
+                        if hit.t < t_max(&closest) {
+                            closest = Some(hit);
+                        }
+                    }
+                }
+                continue;
+            }
+
+            let limit: f64 = t_max(&closest);
+            let left = self.nodes[node.first].bounds.hit(r, limit);
+            let right = self.nodes[node.first + 1].bounds.hit(r, limit);
+            // The stack pops the last push first, so push the farther child first
+            match (left, right) {
+                (Some(t_left), Some(t_right)) if t_left <= t_right => {
+                    stack.push((node.first + 1, t_right));
+                    stack.push((node.first, t_left));
+                }
+                (Some(t_left), Some(t_right)) => {
+                    stack.push((node.first, t_left));
+                    stack.push((node.first + 1, t_right));
+                }
+                (Some(t_left), None) => stack.push((node.first, t_left)),
+                (None, Some(t_right)) => stack.push((node.first + 1, t_right)),
+                (None, None) => {}
+            }
+        }
+        closest
+    }
+}
+
+fn intersect_primitive(
+    primitive: &Primitive,
+    objects: &[Object],
+    meshes: &[Mesh],
+    instances: &[Instance],
+    r: &Ray,
+) -> Option<Hit> {
+    match primitive {
+        Primitive::Object(index) => objects[*index].intersect(r),
+        Primitive::Instance(index) => instances[*index].intersect(r),
+        Primitive::Triangle { mesh, index } => meshes[*mesh].intersect_triangle(r, *index),
+        Primitive::Point { mesh, index } => meshes[*mesh].intersect_point(r, *index),
+    }
+}
+
+fn primitive_bounds(
+    primitive: &Primitive,
+    objects: &[Object],
+    meshes: &[Mesh],
+    instances: &[Instance],
+) -> Option<Aabb> {
+    match primitive {
+        Primitive::Object(index) => objects[*index].bounds(),
+        Primitive::Instance(index) => instances[*index].bounds(),
+        Primitive::Triangle { mesh, index } => {
+            let (a, b, c) = meshes[*mesh].triangle(*index);
+            Some(Aabb::from_points(&[a, b, c]))
+        }
+        Primitive::Point { mesh, index } => {
+            let radius: f64 = meshes[*mesh].point_radius()?;
+            let padding = Vec3 {
+                x: radius,
+                y: radius,
+                z: radius,
+            };
+            let vertex: Vec3 = meshes[*mesh].vertices()[*index];
+            Some(Aabb::new(vertex - padding, vertex + padding))
+        }
+    }
+}
+
+// Faces of a mesh, or the points of a point cloud
+fn mesh_primitive_count(mesh: &Mesh) -> usize {
+    match (mesh.triangle_count(), mesh.point_radius()) {
+        (0, Some(_)) => mesh.vertices().len(),
+        (count, _) => count,
+    }
+}
+
+fn layout(objects: &[Object], meshes: &[Mesh], instances: &[Instance]) -> Vec<usize> {
+    [objects.len(), instances.len()]
+        .into_iter()
+        .chain(meshes.iter().map(mesh_primitive_count))
+        .collect()
+}
+
+// Sorts `items` into two halves along the cheapest binned SAH split and returns where
+// the second half starts, or None if the node is better off as a leaf
+fn split(items: &mut [(Primitive, Aabb)], bounds: &Aabb) -> Option<usize> {
+    if items.len() <= 1 {
+        return None;
+    }
+    let centroids: Aabb = items.iter().fold(Aabb::empty(), |centroids, (_, item)| {
+        centroids.grow(&item.center())
+    });
+    let bin = |item: &Aabb, axis: usize| -> usize {
+        let min: f64 = centroids.min[axis];
+        let extent: f64 = centroids.max[axis] - min;
+        let position: f64 = (item.center()[axis] - min) / extent;
+        ((position * BINS as f64) as usize).min(BINS - 1)
+    };
+
+    // Cheapest (cost, axis, first bin of the right half)
+    let mut best: Option<(f64, usize, usize)> = None;
+    for axis in 0..3 {
+        if centroids.max[axis] - centroids.min[axis] <= 0.0 {
+            continue;
+        }
+        let mut bins: [(Aabb, usize); BINS] = [(Aabb::empty(), 0); BINS];
+        for (_, item) in items.iter() {
+            let b: usize = bin(item, axis);
+            bins[b].0 = bins[b].0.union(item);
+            bins[b].1 += 1;
+        }
+
+        // Sweep from the right to get the cost of everything past each split
+        let mut right_costs: [f64; BINS] = [0.0; BINS];
+        let (mut right_bounds, mut right_count) = (Aabb::empty(), 0);
+        for b in (1..BINS).rev() {
+            right_bounds = right_bounds.union(&bins[b].0);
+            right_count += bins[b].1;
+            right_costs[b] = right_bounds.surface_area() * right_count as f64;
+        }
+        let (mut left_bounds, mut left_count) = (Aabb::empty(), 0);
+        for b in 1..BINS {
+            left_bounds = left_bounds.union(&bins[b - 1].0);
+            left_count += bins[b - 1].1;
+            if left_count == 0 || left_count == items.len() {
+                continue;
+            }
+            let cost: f64 = TRAVERSAL_COST
+                + (left_bounds.surface_area() * left_count as f64 + right_costs[b])
+                    / bounds.surface_area().max(f64::MIN_POSITIVE);
+            if best.is_none_or(|(best_cost, _, _)| cost < best_cost) {
+                best = Some((cost, axis, b));
+            }
+        }
+    }
+
+    // Every centroid in the same place, nothing to split
+    let (cost, axis, split_bin) = best?;
+    if cost >= items.len() as f64 && items.len() <= MAX_LEAF_SIZE {
+        return None;
+    }
+
+    let mut middle: usize = 0;
+    for i in 0..items.len() {
+        if bin(&items[i].1, axis) < split_bin {
+            items.swap(i, middle);
+            middle += 1;
+        }
+    }
+    Some(middle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rotation::Quaternion;
+    use crate::scene::Scene;
+
+    // Small deterministic generator so failures can be reproduced
+    struct Lcg(u64);
+
+    impl Lcg {
+        fn next(&mut self) -> f64 {
+            self.0 = self
+                .0
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            (self.0 >> 11) as f64 / (1u64 << 53) as f64
+        }
+        fn range(&mut self, min: f64, max: f64) -> f64 {
+            min + (max - min) * self.next()
+        }
+        fn vec3(&mut self, min: f64, max: f64) -> Vec3 {
+            Vec3 {
+                x: self.range(min, max),
+                y: self.range(min, max),
+                z: self.range(min, max),
+            }
+        }
+    }
+
+    fn random_scene(rng: &mut Lcg) -> Scene {
+        let mut scene = Scene::new();
+        for i in 0..120 {
+            let center: Vec3 = rng.vec3(-10.0, 10.0);
+            let size: f64 = rng.range(0.1, 1.0);
+            let offset: Vec3 = rng.vec3(-1.0, 1.0);
+            let orientation: Quaternion = Quaternion::euler_to_quaternion(rng.vec3(-3.0, 3.0));
+            scene.add(match i % 10 {
+                0 => Object::new_sphere(&center, &size),
+                1 => Object::new_triangle(
+                    &center,
+                    &(center + offset),
+                    &(center + rng.vec3(-1.0, 1.0)),
+                ),
+                2 => {
+                    Object::new_oriented_box(&center, &(center + rng.vec3(0.1, 1.0)), &orientation)
+                }
+                3 => Object::new_cylinder(&center, &(center + offset), size),
+                4 => Object::new_cone(&center, &(center + offset), size, size / 2.0),
+                5 => Object::new_capsule(&center, &(center + offset), size),
+                6 => Object::new_torus(&center, size, size / 3.0, &orientation),
+                7 => Object::new_quad(&center, &offset, &rng.vec3(-1.0, 1.0)),
+                8 => Object::new_disk(&center, &offset, size),
+                _ => Object::new_finite_plane(&center, &offset, size, size * 2.0),
+            });
+        }
+        scene.add(Object::new_plane(
+            &Vec3 {
+                x: 0.0,
+                y: -12.0,
+                z: 0.0,
+            },
+            &Vec3 {
+                x: 0.1,
+                y: 1.0,
+                z: 0.0,
+            },
+        ));
+
+        let vertices: Vec<Vec3> = (0..300).map(|_| rng.vec3(-8.0, 8.0)).collect();
+        let indices: Vec<[usize; 3]> = (0..100).map(|i| [3 * i, 3 * i + 1, 3 * i + 2]).collect();
+        scene.add_mesh(Mesh::new(vertices, indices));
+        let mut points = Mesh::new((0..200).map(|_| rng.vec3(-6.0, 6.0)).collect(), Vec::new());
+        points.set_point_radius(0.2);
+        scene.add_mesh(points);
+        scene
+    }
+
+    // Fires random rays at the scene and checks `bvh` finds the same closest hits
+    fn assert_matches_brute_force(scene: &Scene, bvh: &Bvh, rng: &mut Lcg) {
+        let mut hits: usize = 0;
+        for _ in 0..5000 {
+            let r = Ray::new(rng.vec3(-15.0, 15.0), rng.vec3(-1.0, 1.0));
+            let expected: Option<Hit> = scene.intersect_brute_force(&r);
+            let actual: Option<Hit> = bvh.intersect(&scene.objects, &scene.meshes, &[], &r);
+            match (expected, actual) {
+                (None, None) => {}
+                (Some(expected), Some(actual)) => {
+                    hits += 1;
+                    assert!(
+                        (expected.t - actual.t).abs() < 1e-9,
+                        "t {} != {} for {:?}",
+                        actual.t,
+                        expected.t,
+                        r
+                    );
+                }
+                _ => panic!(
+                    "brute force {:?} but BVH {:?} for {:?}",
+                    expected, actual, r
+                ),
+            }
+        }
+        // Make sure the rays actually exercised the tree
+        assert!(hits > 1000, "only {} rays hit anything", hits);
+    }
+
+    #[test]
+    fn matches_brute_force() {
+        let mut rng = Lcg(7);
+        let scene: Scene = random_scene(&mut rng);
+        let bvh = Bvh::build(&scene.objects, &scene.meshes, &[]);
+        assert_matches_brute_force(&scene, &bvh, &mut rng);
+    }
+
+    #[test]
+    fn refit_matches_brute_force_after_moving() {
+        let mut rng = Lcg(11);
+        let mut scene: Scene = random_scene(&mut rng);
+        let mut bvh = Bvh::build(&scene.objects, &scene.meshes, &[]);
+
+        for _ in 0..3 {
+            for object in scene.objects.iter_mut() {
+                *object = object
+                    .move_to(rng.vec3(-1.0, 1.0))
+                    .rotate_around_center(rng.vec3(-1.0, 1.0));
+            }
+            scene.meshes[0] = scene.meshes[0].rotate_around_center(rng.vec3(-1.0, 1.0));
+            assert!(bvh.refit(&scene.objects, &scene.meshes, &[]));
+            assert_matches_brute_force(&scene, &bvh, &mut rng);
+        }
+    }
+
+    #[test]
+    fn scattering_objects_degrades_the_tree() {
+        let mut rng = Lcg(3);
+        let mut scene: Scene = random_scene(&mut rng);
+        let mut bvh = Bvh::build(&scene.objects, &scene.meshes, &[]);
+        assert!(!bvh.degraded());
+
+        // Swap objects around so every box stretches across the scene. The last
+        // object is the infinite plane, which stays where it is.
+        let count: usize = scene.objects.len() - 1;
+        for i in 0..count / 2 {
+            scene.objects.swap(i, count - 1 - i);
+        }
+        assert!(bvh.refit(&scene.objects, &scene.meshes, &[]));
+        assert!(bvh.degraded());
+
+        scene.add(Object::new_sphere(&rng.vec3(-1.0, 1.0), &0.5));
+        assert!(!bvh.refit(&scene.objects, &scene.meshes, &[]));
+    }
+
+    #[test]
+    fn empty_scene_has_no_hits() {
+        let bvh = Bvh::build(&[], &[], &[]);
+        let r = Ray::new(
+            Vec3 {
+                x: 0.0,
+                y: 0.0,
+                z: 0.0,
+            },
+            Vec3 {
+                x: 0.0,
+                y: 0.0,
+                z: -1.0,
+            },
+        );
+        assert!(bvh.intersect(&[], &[], &[], &r).is_none());
+    }
+}
@@ -22,6 +22,7 @@ pub struct Camera {
     viewport_upper_left: Vec3,
     pixel00_loc: Vec3,
     view_dir: Vec3,
+    orientation: Quaternion,
 
     stretch: Option<Stretch>,
 }
@@ -85,6 +86,7 @@ impl Camera {
             viewport_upper_left,
             pixel00_loc,
             view_dir,
+            orientation: Quaternion::new(1.0, 0.0, 0.0, 0.0),
             stretch: Option::None,
         }
     }
@@ -100,29 +102,35 @@ impl Camera {
         self.viewport_width =
             self.viewport_height * (self.image_width as f64 / self.image_height as f64);
 
-        self.viewport_u = Vec3 {
+        self.viewport_u = self.orientation.rotate(&Vec3 {
             x: self.viewport_width,
             y: 0.0,
             z: 0.0,
-        };
+        });
 
-        self.viewport_v = Vec3 {
+        self.viewport_v = self.orientation.rotate(&Vec3 {
             x: 0.0,
             y: -self.viewport_height,
             z: 0.0,
-        };
+        });
 
+        let view_dir: Vec3 = self.orientation.rotate(&self.view_dir);
         match self.stretch {
             None => {
                 self.pixel_delta_u = self.viewport_u / self.image_width as f64;
                 self.pixel_delta_v = self.viewport_v / self.image_height as f64;
+
+                self.viewport_upper_left = self.camera_pos
+                    - (view_dir * self.focal_length)
+                    - (self.viewport_u / 2.0)
+                    - (self.viewport_v / 2.0);
             }
             Some(stretch) => {
                 self.pixel_delta_u = (self.viewport_u / self.image_width as f64) * stretch.0;
                 self.pixel_delta_v = (self.viewport_v / self.image_height as f64) * stretch.1;
 
                 self.viewport_upper_left = self.camera_pos
-                    - (self.view_dir * self.focal_length)
+                    - (view_dir * self.focal_length)
                     - (self.viewport_u * stretch.0 / 2.0)
                     - (self.viewport_v * stretch.1 / 2.0);
             }
@@ -151,6 +159,14 @@ impl Camera {
         self.stretch = Some(stretch);
         self.update();
     }
+    pub fn set_position(&mut self, camera_pos: Vec3) {
+        self.camera_pos = camera_pos;
+        self.update();
+    }
+    pub fn set_orientation(&mut self, orientation: Quaternion) {
+        self.orientation = orientation;
+        self.update();
+    }
 
     pub fn image_height(&self) -> i32 {
         self.image_height
@@ -170,31 +186,37 @@ impl Camera {
     pub fn camera_pos(&self) -> Vec3 {
         self.camera_pos
     }
+    pub fn orientation(&self) -> Quaternion {
+        self.orientation
+    }
+
+    // View basis: the camera looks down -z and +y is up before rotation
+    pub fn forward(&self) -> Vec3 {
+        self.orientation.rotate(&Vec3 {
+            x: 0.0,
+            y: 0.0,
+            z: -1.0,
+        })
+    }
+    pub fn right(&self) -> Vec3 {
+        self.orientation.rotate(&Vec3 {
+            x: 1.0,
+            y: 0.0,
+            z: 0.0,
+        })
+    }
+    pub fn up(&self) -> Vec3 {
+        self.orientation.rotate(&Vec3 {
+            x: 0.0,
+            y: 1.0,
+            z: 0.0,
+        })
+    }
 
     pub fn rotate_around_center(&mut self, euler: Vec3) {
         let quaternion: Quaternion = Quaternion::euler_to_quaternion(euler);
-        self.viewport_u = self
-            .viewport_u
-            .rotate_around_point(&self.camera_pos, &quaternion);
-        self.viewport_v = self
-            .viewport_v
-            .rotate_around_point(&self.camera_pos, &quaternion);
-        self.viewport_upper_left = self
-            .viewport_upper_left
-            .rotate_around_point(&self.camera_pos, &quaternion);
-
-        match self.stretch {
-            None => {
-                self.pixel_delta_u = self.viewport_u / self.image_width as f64;
-                self.pixel_delta_v = self.viewport_v / self.image_height as f64;
-            }
-            Some(stretch) => {
-                self.pixel_delta_u = (self.viewport_u / self.image_width as f64) * stretch.0;
-                self.pixel_delta_v = (self.viewport_v / self.image_height as f64) * stretch.1;
-            }
-        }
-        self.pixel00_loc =
-            self.viewport_upper_left + 0.5 * (self.pixel_delta_u + self.pixel_delta_v);
+        self.orientation = quaternion * self.orientation;
+        self.update();
     }
 }
 // TODO camera
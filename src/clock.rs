@@ -0,0 +1,168 @@
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Longest step handed to animation, so a stall (e.g. a suspended process) doesn't
+// make everything jump
+const MAX_DT: f64 = 0.25;
+
+// Source of time for everything in the render loop, in seconds since the clock started
+pub trait Clock {
+    fn now(&self) -> f64;
+    fn sleep(&mut self, seconds: f64);
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct RealClock {
+    start: Instant,
+}
+
+impl RealClock {
+    pub fn new() -> RealClock {
+        RealClock {
+            start: Instant::now(),
+        }
+    }
+}
+
+impl Clock for RealClock {
+    fn now(&self) -> f64 {
+        self.start.elapsed().as_secs_f64()
+    }
+    fn sleep(&mut self, seconds: f64) {
+        thread::sleep(Duration::from_secs_f64(seconds));
+    }
+}
+
+// Time only moves when told to. Sleeping advances it instantly, so a FrameClock
+// driven by it steps exactly 1 / target_fps every frame.
+#[cfg(test)]
+#[derive(Debug, Copy, Clone)]
+pub struct ManualClock {
+    time: f64,
+}
+
+#[cfg(test)]
+impl ManualClock {
+    pub fn new() -> ManualClock {
+        ManualClock { time: 0.0 }
+    }
+    pub fn advance(&mut self, seconds: f64) {
+        self.time += seconds;
+    }
+}
+
+#[cfg(test)]
+impl Clock for ManualClock {
+    fn now(&self) -> f64 {
+        self.time
+    }
+    fn sleep(&mut self, seconds: f64) {
+        self.advance(seconds);
+    }
+}
+
+// Paces the main loop to a target frame rate and measures how long frames take
+#[derive(Debug, Copy, Clone)]
+pub struct FrameClock<C: Clock = RealClock> {
+    clock: C,
+    target_fps: f64,
+    last_tick: f64,
+    frame_time: f64, // seconds of actual work in the last frame, excluding the sleep
+    average_dt: f64, // smoothed seconds per frame
+}
+
+impl FrameClock {
+    pub fn new(target_fps: f64) -> FrameClock {
+        FrameClock::with_clock(RealClock::new(), target_fps)
+    }
+}
+
+impl<C: Clock> FrameClock<C> {
+    pub fn with_clock(clock: C, target_fps: f64) -> FrameClock<C> {
+        FrameClock {
+            last_tick: clock.now(),
+            clock,
+            target_fps,
+            frame_time: 0.0,
+            average_dt: 1.0 / target_fps,
+        }
+    }
+
+    // Starts a frame and returns the delta-time animation should advance by
+    pub fn tick(&mut self) -> f64 {
+        let now = self.clock.now();
+        let elapsed = now - self.last_tick;
+        self.last_tick = now;
+
+        // Averaging frame times rather than rates keeps one very short frame from spiking it
+        self.average_dt += (elapsed - self.average_dt) * 0.1;
+        elapsed.min(MAX_DT)
+    }
+
+    // Ends a frame, sleeping away whatever is left of the target frame time
+    pub fn end_frame(&mut self) {
+        self.frame_time = self.clock.now() - self.last_tick;
+        let remaining = 1.0 / self.target_fps - self.frame_time;
+        if remaining > 0.0 {
+            self.clock.sleep(remaining);
+        }
+    }
+
+    #[cfg(test)]
+    pub fn clock(&self) -> &C {
+        &self.clock
+    }
+    #[cfg(test)]
+    pub fn clock_mut(&mut self) -> &mut C {
+        &mut self.clock
+    }
+    // Seconds since the clock started, for animations driven by absolute time
+    pub fn time(&self) -> f64 {
+        self.clock.now()
+    }
+    pub fn fps(&self) -> f64 {
+        1.0 / self.average_dt
+    }
+
+    pub fn hud(&self, rays: usize, width: i32, height: i32) -> String {
+        let rays_per_second = if self.frame_time > 0.0 {
+            rays as f64 / self.frame_time
+        } else {
+            0.0
+        };
+        format!(
+            "FPS {:5.1} | frame {:6.2} ms | {:6.2} Mrays/s | {}x{}",
+            self.fps(),
+            self.frame_time * 1000.0,
+            rays_per_second / 1e6,
+            width,
+            height
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn manual_clock_steps_exactly_one_frame() {
+        let mut clock: FrameClock<ManualClock> = FrameClock::with_clock(ManualClock::new(), 30.0);
+        assert_eq!(clock.tick(), 0.0);
+        clock.end_frame();
+        for frame in 1..=10 {
+            assert!((clock.tick() - 1.0 / 30.0).abs() < 1e-12);
+            assert!((clock.time() - frame as f64 / 30.0).abs() < 1e-12);
+            clock.end_frame();
+        }
+    }
+
+    #[test]
+    fn stalls_are_clamped() {
+        let mut clock: FrameClock<ManualClock> = FrameClock::with_clock(ManualClock::new(), 30.0);
+        clock.tick();
+        clock.clock_mut().advance(5.0);
+        assert_eq!(clock.tick(), MAX_DT);
+        assert_eq!(clock.time(), 5.0);
+    }
+}
@@ -0,0 +1,417 @@
+use crate::camera::Camera;
+use crate::input_handler::{Arrow, InputEvent, MouseButton};
+use crate::rotation::{Axes, EulerOrder, Quaternion};
+use crate::vector::Vec3;
+use libm::{exp, sqrt};
+use std::f64::consts::FRAC_PI_2;
+
+// Indices into FlyController::held
+const FORWARD: usize = 0;
+const BACK: usize = 1;
+const LEFT: usize = 2;
+const RIGHT: usize = 3;
+const UP: usize = 4;
+const DOWN: usize = 5;
+const LOOK_UP: usize = 6;
+const LOOK_DOWN: usize = 7;
+const LOOK_LEFT: usize = 8;
+const LOOK_RIGHT: usize = 9;
+
+// A terminal only reports presses (and auto-repeats), never releases, so a key
+// counts as held for a short while after its last event
+const KEY_HOLD: f64 = 0.15;
+const MAX_PITCH: f64 = FRAC_PI_2 - 0.01;
+
+#[derive(Debug, Copy, Clone)]
+pub struct FlyController {
+    speed: f64,       // units per second
+    sensitivity: f64, // radians per mouse cell
+    look_speed: f64,  // radians per second for the arrow keys
+    smoothing: f64,   // mouse smoothing time constant in seconds, 0 disables
+
+    position: Vec3,
+    yaw: f64,   // positive turns right
+    pitch: f64, // positive looks down
+
+    mouse: (f64, f64),
+    smoothed_mouse: (f64, f64),
+    held: [f64; 10],
+}
+
+impl FlyController {
+    pub fn new(camera: &Camera) -> FlyController {
+        let mut fly = FlyController {
+            speed: 2.0,
+            sensitivity: 0.02,
+            look_speed: 1.5,
+            smoothing: 0.05,
+            position: camera.camera_pos(),
+            yaw: 0.0,
+            pitch: 0.0,
+            mouse: (0.0, 0.0),
+            smoothed_mouse: (0.0, 0.0),
+            held: [0.0; 10],
+        };
+        fly.sync(camera);
+        fly
+    }
+
+    // Takes over from wherever the camera is now, so switching to flying
+    // doesn't snap the view. Flying has no roll, so any is dropped.
+    pub fn sync(&mut self, camera: &Camera) {
+        // update() builds the orientation as a yaw then a pitch, both turning
+        // the opposite way to new_from_euler
+        let angles: Vec3 = camera
+            .orientation()
+            .to_euler(EulerOrder::Intrinsic(Axes::YXZ));
+        self.position = camera.camera_pos();
+        self.yaw = -angles.y;
+        self.pitch = (-angles.x).clamp(-MAX_PITCH, MAX_PITCH);
+        self.mouse = (0.0, 0.0);
+        self.smoothed_mouse = (0.0, 0.0);
+        self.held = [0.0; 10];
+    }
+
+    fn handle(&mut self, event: &InputEvent) {
+        let key = match event {
+            InputEvent::Keyboard(c) => match c.to_ascii_lowercase() {
+                'w' => FORWARD,
+                's' => BACK,
+                'a' => LEFT,
+                'd' => RIGHT,
+                'e' => UP,
+                'q' => DOWN,
+                _ => return,
+            },
+            InputEvent::Arrow(arrow) => match arrow {
+                Arrow::Up => LOOK_UP,
+                Arrow::Down => LOOK_DOWN,
+                Arrow::Left => LOOK_LEFT,
+                Arrow::Right => LOOK_RIGHT,
+            },
+            InputEvent::MouseMove(dx, dy) => {
+                self.mouse.0 += dx;
+                self.mouse.1 += dy;
+                return;
+            }
+            _ => return,
+        };
+        self.held[key] = KEY_HOLD;
+    }
+
+    fn axis(&self, positive: usize, negative: usize) -> f64 {
+        let pressed = |key: usize| if self.held[key] > 0.0 { 1.0 } else { 0.0 };
+        pressed(positive) - pressed(negative)
+    }
+
+    pub fn update(&mut self, camera: &mut Camera, events: &[InputEvent], dt: f64) {
+        for event in events {
+            self.handle(event);
+        }
+
+        // Exponential smoothing that behaves the same at any frame rate
+        let alpha = if self.smoothing > 0.0 {
+            1.0 - exp(-dt / self.smoothing)
+        } else {
+            1.0
+        };
+        self.smoothed_mouse.0 += (self.mouse.0 - self.smoothed_mouse.0) * alpha;
+        self.smoothed_mouse.1 += (self.mouse.1 - self.smoothed_mouse.1) * alpha;
+        self.mouse = (0.0, 0.0);
+
+        self.yaw += self.smoothed_mouse.0 * self.sensitivity
+            + self.axis(LOOK_RIGHT, LOOK_LEFT) * self.look_speed * dt;
+        self.pitch += self.smoothed_mouse.1 * self.sensitivity
+            + self.axis(LOOK_DOWN, LOOK_UP) * self.look_speed * dt;
+        self.pitch = self.pitch.clamp(-MAX_PITCH, MAX_PITCH);
+
+        // Pitch about the camera's own x axis, then yaw about the world y axis
+        let orientation: Quaternion = Quaternion::euler_to_quaternion(Vec3 {
+            x: 0.0,
+            y: self.yaw,
+            z: 0.0,
+        }) * Quaternion::euler_to_quaternion(Vec3 {
+            x: self.pitch,
+            y: 0.0,
+            z: 0.0,
+        });
+        camera.set_orientation(orientation);
+
+        let direction: Vec3 = camera.forward() * self.axis(FORWARD, BACK)
+            + camera.right() * self.axis(RIGHT, LEFT)
+            + camera.up() * self.axis(UP, DOWN);
+        let length: f64 = direction.length();
+        if length > 0.0 {
+            self.position += direction * (self.speed * dt / length);
+        }
+        camera.set_position(self.position);
+
+        for held in self.held.iter_mut() {
+            *held -= dt;
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct OrbitController {
+    rotate_step: f64, // radians per arrow key press
+    zoom_step: f64,   // distance factor per scroll notch or +/- press
+    pan_speed: f64,   // units per mouse cell at distance 1
+
+    target: Vec3,
+    distance: f64,
+    orientation: Quaternion,
+    pan_mode: bool,
+    screen: (f64, f64), // columns, lines
+}
+
+impl OrbitController {
+    pub fn new(target: Vec3, distance: f64, columns: f64, lines: f64) -> OrbitController {
+        OrbitController {
+            rotate_step: 0.1,
+            zoom_step: 1.1,
+            pan_speed: 0.02,
+            target,
+            distance,
+            orientation: Quaternion::new(1.0, 0.0, 0.0, 0.0),
+            pan_mode: false,
+            screen: (columns, lines),
+        }
+    }
+
+    pub fn set_target(&mut self, target: Vec3) {
+        self.target = target;
+    }
+    pub fn set_distance(&mut self, distance: f64) {
+        self.distance = distance;
+    }
+    pub fn set_orientation(&mut self, orientation: Quaternion) {
+        self.orientation = orientation;
+    }
+
+    // Maps a terminal cell onto the virtual arcball, in view space
+    fn arcball_point(&self, cell: (f64, f64)) -> Vec3 {
+        let x = 2.0 * cell.0 / self.screen.0 - 1.0;
+        let y = 1.0 - 2.0 * cell.1 / self.screen.1;
+        let d = x * x + y * y;
+        if d <= 1.0 {
+            Vec3 {
+                x,
+                y,
+                z: sqrt(1.0 - d),
+            }
+        } else {
+            let length = sqrt(d);
+            Vec3 {
+                x: x / length,
+                y: y / length,
+                z: 0.0,
+            }
+        }
+    }
+
+    // Spins the scene by `rotation` (given in view space) by moving the camera the other way
+    fn orbit(&mut self, rotation: Quaternion) {
+        self.orientation = (self.orientation * rotation.conjugate()).normalize();
+    }
+
+    fn pan(&mut self, dx: f64, dy: f64) {
+        let right: Vec3 = self.orientation.rotate(&Vec3 {
+            x: 1.0,
+            y: 0.0,
+            z: 0.0,
+        });
+        let up: Vec3 = self.orientation.rotate(&Vec3 {
+            x: 0.0,
+            y: 1.0,
+            z: 0.0,
+        });
+        let scale = self.pan_speed * self.distance;
+        self.target -= right * (dx * scale) - up * (dy * scale);
+    }
+
+    fn dolly(&mut self, notches: f64) {
+        self.distance /= libm::pow(self.zoom_step, notches);
+        self.distance = self.distance.max(0.05);
+    }
+
+    fn handle(&mut self, event: &InputEvent) {
+        match event {
+            InputEvent::MouseDrag(MouseButton::Left, from, to) if !self.pan_mode => {
+                // Shoemake's arcball: the quaternion between the two sphere points
+                let p0: Vec3 = self.arcball_point(*from);
+                let p1: Vec3 = self.arcball_point(*to);
+                let axis: Vec3 = Vec3::cross(&p0, &p1);
+                let rotation = Quaternion::new(Vec3::dot(&p0, &p1), axis.x, axis.y, axis.z);
+                // Normalizing a quaternion with nothing in it would leave NaN in
+                // the orientation for good, so leave such a drag out
+                let length: f64 = sqrt(Quaternion::dot(&rotation, &rotation));
+                if !length.is_finite() || length < 1e-9 {
+                    return;
+                }
+                self.orbit(rotation.normalize());
+            }
+            InputEvent::MouseDrag(_, from, to) => self.pan(to.0 - from.0, to.1 - from.1),
+            InputEvent::Scroll(notches) => self.dolly(*notches),
+            InputEvent::Keyboard('+') | InputEvent::Keyboard('=') => self.dolly(1.0),
+            InputEvent::Keyboard('-') => self.dolly(-1.0),
+            InputEvent::Keyboard('p') => self.pan_mode = !self.pan_mode,
+            InputEvent::Arrow(arrow) if self.pan_mode => {
+                let cells = self.rotate_step / self.pan_speed;
+                match arrow {
+                    Arrow::Up => self.pan(0.0, -cells),
+                    Arrow::Down => self.pan(0.0, cells),
+                    Arrow::Left => self.pan(-cells, 0.0),
+                    Arrow::Right => self.pan(cells, 0.0),
+                }
+            }
+            InputEvent::Arrow(arrow) => {
+                let (yaw, pitch) = match arrow {
+                    Arrow::Up => (0.0, -self.rotate_step),
+                    Arrow::Down => (0.0, self.rotate_step),
+                    Arrow::Left => (-self.rotate_step, 0.0),
+                    Arrow::Right => (self.rotate_step, 0.0),
+                };
+                let rotation: Quaternion = Quaternion::euler_to_quaternion(Vec3 {
+                    x: pitch,
+                    y: yaw,
+                    z: 0.0,
+                });
+                self.orbit(rotation);
+            }
+            _ => {}
+        }
+    }
+
+    pub fn update(&mut self, camera: &mut Camera, events: &[InputEvent]) {
+        for event in events {
+            self.handle(event);
+        }
+        let offset: Vec3 = self.orientation.rotate(&Vec3 {
+            x: 0.0,
+            y: 0.0,
+            z: self.distance,
+        });
+        camera.set_orientation(self.orientation);
+        camera.set_position(self.target + offset);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::{FrameClock, ManualClock};
+    use crate::input_handler::InputReplay;
+
+    // Runs the fly controller for a number of frames at 30 fps, feeding it the
+    // replay as the manual clock reaches each event
+    fn fly(replay: &mut InputReplay, frames: usize, fly: &mut FlyController) -> Camera {
+        let mut camera: Camera = Camera::new();
+        let mut clock: FrameClock<ManualClock> = FrameClock::with_clock(ManualClock::new(), 30.0);
+        for _ in 0..frames {
+            let dt: f64 = clock.tick();
+            let events: Vec<InputEvent> = replay.poll(clock.clock());
+            fly.update(&mut camera, &events, dt);
+            clock.end_frame();
+        }
+        camera
+    }
+
+    #[test]
+    fn held_key_moves_at_the_set_speed() {
+        // A terminal repeats a held key every 0.1 s or so
+        let mut replay: InputReplay = InputReplay::from_events(
+            (0..10)
+                .map(|i| (i as f64 / 10.0, InputEvent::Keyboard('w')))
+                .collect(),
+        );
+        let start: Vec3 = Camera::new().camera_pos();
+        let mut controller: FlyController = FlyController::new(&Camera::new());
+        let camera: Camera = fly(&mut replay, 31, &mut controller);
+
+        // The first frame has no time behind it, the other 30 make a second
+        let moved: Vec3 = camera.camera_pos() - start;
+        assert!(moved.approx_eq(
+            &Vec3 {
+                x: 0.0,
+                y: 0.0,
+                z: -2.0,
+            },
+            1e-9
+        ));
+    }
+
+    #[test]
+    fn recorded_mouse_turns_the_camera() {
+        let mut recorder: InputReplay = InputReplay::new();
+        let mut clock: ManualClock = ManualClock::new();
+        clock.advance(0.5);
+        recorder.record(&clock, &[InputEvent::MouseMove(10.0, 0.0)]);
+        assert_eq!(recorder.events().len(), 1);
+
+        let mut controller: FlyController = FlyController::new(&Camera::new());
+        controller.smoothing = 0.0;
+        let camera: Camera = fly(&mut recorder, 30, &mut controller);
+        // 10 cells at 0.02 radians each, to the right
+        assert!(camera.forward().approx_eq(
+            &Vec3 {
+                x: libm::sin(0.2),
+                y: 0.0,
+                z: -libm::cos(0.2),
+            },
+            1e-9
+        ));
+
+        // Played back again from the start, the same turn happens once more
+        recorder.rewind();
+        let again: Camera = fly(&mut recorder, 30, &mut controller);
+        assert!(again.forward().approx_eq(
+            &Vec3 {
+                x: libm::sin(0.4),
+                y: 0.0,
+                z: -libm::cos(0.4),
+            },
+            1e-9
+        ));
+    }
+
+    #[test]
+    fn drag_across_the_whole_arcball() {
+        // From one side of the rim to the other, a whole turn that leaves the view as it was
+        let mut orbit: OrbitController = OrbitController::new(
+            Vec3 {
+                x: 0.0,
+                y: 0.0,
+                z: 0.0,
+            },
+            2.0,
+            80.0,
+            24.0,
+        );
+        let mut camera: Camera = Camera::new();
+        orbit.update(&mut camera, &[]);
+        let forward: Vec3 = camera.forward();
+        orbit.update(
+            &mut camera,
+            &[InputEvent::MouseDrag(
+                MouseButton::Left,
+                (0.0, 12.0),
+                (80.0, 12.0),
+            )],
+        );
+        assert!(camera.forward().approx_eq(&forward, 1e-9));
+
+        // A screen with no size yet puts the points nowhere
+        let mut orbit: OrbitController = OrbitController::new(forward, 2.0, 0.0, 0.0);
+        orbit.update(
+            &mut camera,
+            &[InputEvent::MouseDrag(
+                MouseButton::Left,
+                (0.0, 0.0),
+                (1.0, 1.0),
+            )],
+        );
+        assert!(camera.forward().approx_eq(&forward, 1e-9));
+        assert!(camera.camera_pos().x.is_finite());
+    }
+}
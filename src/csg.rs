@@ -0,0 +1,359 @@
+use crate::aabb::Aabb;
+use crate::object::{Hit, Object, ObjectTrait};
+use crate::ray::Ray;
+use crate::vector::Vec3;
+
+const MAX_CROSSINGS: usize = 16; // per object, a torus has at most four
+const BIAS: f64 = 1e-7; // distance to step past a surface before looking for the next one
+
+#[derive(Debug, Copy, Clone)]
+pub enum Operation {
+    Union,
+    Intersection,
+    Difference, // the first solid with the second carved out of it
+}
+
+// A solid made of objects combined with boolean operations. The objects should
+// be closed, a ray that never crosses one counts as outside it.
+#[derive(Debug, Clone)]
+pub enum Csg {
+    Object(Object),
+    Combine {
+        operation: Operation,
+        a: Box<Csg>,
+        b: Box<Csg>,
+        bounds: Option<Aabb>, // None if unbounded
+    },
+}
+
+// Where the ray crosses a solid's surface, with the solid's outward normal
+#[derive(Debug, Copy, Clone)]
+struct Crossing {
+    t: f64,
+    normal: Vec3,
+}
+
+// Stretch of the ray inside a solid. One the ray starts in begins at negative
+// infinity, and one it never leaves ends at infinity.
+#[derive(Debug, Copy, Clone)]
+struct Span {
+    entry: Crossing,
+    exit: Crossing,
+}
+
+impl Operation {
+    fn contains(&self, in_a: bool, in_b: bool) -> bool {
+        match self {
+            Operation::Union => in_a || in_b,
+            Operation::Intersection => in_a && in_b,
+            Operation::Difference => in_a && !in_b,
+        }
+    }
+}
+
+impl Csg {
+    pub fn new(object: Object) -> Self {
+        Csg::Object(object)
+    }
+
+    pub fn union(self, other: Csg) -> Self {
+        Csg::combine(Operation::Union, self, other)
+    }
+    pub fn intersection(self, other: Csg) -> Self {
+        Csg::combine(Operation::Intersection, self, other)
+    }
+    pub fn difference(self, other: Csg) -> Self {
+        Csg::combine(Operation::Difference, self, other)
+    }
+    fn combine(operation: Operation, a: Csg, b: Csg) -> Self {
+        let bounds: Option<Aabb> = match (operation, a.bounds(), b.bounds()) {
+            (Operation::Union, Some(a), Some(b)) => Some(a.union(&b)),
+            (Operation::Union, _, _) => None,
+            // Disjoint boxes come out inverted, which is fine since the
+            // intersection is empty then
+            (Operation::Intersection, Some(a), Some(b)) => Some(Aabb::new(
+                Vec3 {
+                    x: a.min.x.max(b.min.x),
+                    y: a.min.y.max(b.min.y),
+                    z: a.min.z.max(b.min.z),
+                },
+                Vec3 {
+                    x: a.max.x.min(b.max.x),
+                    y: a.max.y.min(b.max.y),
+                    z: a.max.z.min(b.max.z),
+                },
+            )),
+            (Operation::Intersection, bounds, None) | (Operation::Intersection, None, bounds) => {
+                bounds
+            }
+            (Operation::Difference, bounds, _) => bounds,
+        };
+        Csg::Combine {
+            operation,
+            a: Box::new(a),
+            b: Box::new(b),
+            bounds,
+        }
+    }
+
+    // Box around the solid, None if it is unbounded
+    pub fn bounds(&self) -> Option<Aabb> {
+        match self {
+            Csg::Object(object) => object.bounds(),
+            Csg::Combine {
+                operation: _,
+                a: _,
+                b: _,
+                bounds,
+            } => *bounds,
+        }
+    }
+
+    // Spans of the ray inside the solid, in order
+    fn spans(&self, r: &Ray) -> Vec<Span> {
+        match self {
+            Csg::Object(object) => object_spans(object, r),
+            Csg::Combine {
+                operation,
+                a,
+                b,
+                bounds,
+            } => {
+                if bounds.is_some_and(|bounds| bounds.hit(r, f64::INFINITY).is_none()) {
+                    return Vec::new();
+                }
+                combine_spans(*operation, &a.spans(r), &b.spans(r))
+            }
+        }
+    }
+
+    fn map(&self, f: &impl Fn(&Object) -> Object) -> Csg {
+        match self {
+            Csg::Object(object) => Csg::Object(f(object)),
+            Csg::Combine {
+                operation,
+                a,
+                b,
+                bounds: _,
+            } => Csg::combine(*operation, a.map(f), b.map(f)),
+        }
+    }
+}
+
+// Walks along the ray from crossing to crossing, telling entries from exits by
+// which way the outward normal faces
+fn object_spans(object: &Object, r: &Ray) -> Vec<Span> {
+    let step: f64 = BIAS / r.direction().length();
+    let mut spans: Vec<Span> = Vec::new();
+    let mut entry: Option<Crossing> = None;
+    let mut t: f64 = 0.0;
+    for _ in 0..MAX_CROSSINGS {
+        let hit: Hit =
+            match object.intersect(&Ray::new(r.origin() + r.direction() * t, r.direction())) {
+                Some(hit) => hit,
+                None => break,
+            };
+        t += hit.t;
+        let crossing = Crossing {
+            t,
+            normal: hit.normal,
+        };
+        if Vec3::dot(&r.direction(), &hit.normal) < 0.0 {
+            entry.get_or_insert(crossing);
+        } else if let Some(start) = entry.take() {
+            spans.push(Span {
+                entry: start,
+                exit: crossing,
+            });
+        } else if spans.is_empty() {
+            // The ray started inside
+            spans.push(Span {
+                entry: Crossing {
+                    t: f64::NEG_INFINITY,
+                    normal: -hit.normal,
+                },
+                exit: crossing,
+            });
+        }
+        t += step;
+    }
+    if let Some(start) = entry {
+        spans.push(Span {
+            entry: start,
+            exit: Crossing {
+                t: f64::INFINITY,
+                normal: -start.normal,
+            },
+        });
+    }
+    spans
+}
+
+// Sweeps both children's span boundaries in ray order, keeping the stretches
+// the operation counts as inside
+fn combine_spans(operation: Operation, a: &[Span], b: &[Span]) -> Vec<Span> {
+    // (crossing, belongs to b, entering)
+    let mut events: Vec<(Crossing, bool, bool)> = Vec::with_capacity(2 * (a.len() + b.len()));
+    for (spans, from_b) in [(a, false), (b, true)] {
+        for span in spans {
+            events.push((span.entry, from_b, true));
+            events.push((span.exit, from_b, false));
+        }
+    }
+    events.sort_by(|x, y| x.0.t.total_cmp(&y.0.t));
+
+    let mut spans: Vec<Span> = Vec::new();
+    let mut entry: Option<Crossing> = None;
+    let (mut in_a, mut in_b) = (false, false);
+    for (crossing, from_b, entering) in events {
+        let was_inside: bool = operation.contains(in_a, in_b);
+        if from_b {
+            in_b = entering;
+        } else {
+            in_a = entering;
+        }
+        let inside: bool = operation.contains(in_a, in_b);
+
+        // The carved surfaces face into the solid that was cut away
+        let crossing = match operation {
+            Operation::Difference if from_b => Crossing {
+                t: crossing.t,
+                normal: -crossing.normal,
+            },
+            _ => crossing,
+        };
+        if !was_inside && inside {
+            entry = Some(crossing);
+        } else if was_inside && !inside {
+            if let Some(start) = entry.take() {
+                spans.push(Span {
+                    entry: start,
+                    exit: crossing,
+                });
+            }
+        }
+    }
+    spans
+}
+
+impl ObjectTrait for Csg {
+    // The first surface the ray crosses, which is where it leaves the solid
+    // when it starts inside
+    fn intersect(&self, r: &Ray) -> Option<Hit> {
+        let crossing: Crossing = self
+            .spans(r)
+            .iter()
+            .flat_map(|span| [span.entry, span.exit])
+            .find(|crossing| crossing.t > f64::EPSILON && crossing.t.is_finite())?;
+        Some(Hit {
+            t: crossing.t,
+            point: r.origin() + r.direction() * crossing.t,
+            normal: crossing.normal,
+            u: 0.0,
+            v: 0.0,
+            occlusion: 0.0,
+        })
+    }
+    fn move_to(&self, v: Vec3) -> Self {
+        self.map(&|object| object.move_to(v))
+    }
+    fn rotate_around_center(&self, euler: Vec3) -> Self {
+        match self.bounds() {
+            Some(bounds) => self.rotate_around_point(euler, bounds.center()),
+            None => self.clone(),
+        }
+    }
+    fn rotate_around_point(&self, euler: Vec3, point: Vec3) -> Self {
+        self.map(&|object| object.rotate_around_point(euler, point))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn point(x: f64, y: f64, z: f64) -> Vec3 {
+        Vec3 { x, y, z }
+    }
+
+    fn assert_near(a: Vec3, b: Vec3) {
+        assert!(a.approx_eq(&b, 1e-9), "{:?} != {:?}", a, b);
+    }
+
+    // A 2 unit cube round the origin with a ball of radius 0.5 carved out of its middle
+    fn hollow_cube() -> Csg {
+        Csg::new(Object::new_box(
+            &point(-1.0, -1.0, -1.0),
+            &point(1.0, 1.0, 1.0),
+        ))
+        .difference(Csg::new(Object::new_sphere(&point(0.0, 0.0, 0.0), &0.5)))
+    }
+
+    #[test]
+    fn ray_starting_inside_an_object() {
+        let ball = Csg::new(Object::new_sphere(&point(0.0, 0.0, 0.0), &1.0));
+        let r = Ray::new(point(0.0, 0.0, 0.0), point(2.0, 0.0, 0.0));
+        let spans: Vec<Span> = ball.spans(&r);
+        assert_eq!(spans.len(), 1);
+        assert_eq!(spans[0].entry.t, f64::NEG_INFINITY);
+        assert!((spans[0].exit.t - 0.5).abs() < 1e-9);
+
+        // The hit is where it leaves, facing on along the ray
+        let hit: Hit = ball.intersect(&r).unwrap();
+        assert!((hit.t - 0.5).abs() < 1e-9);
+        assert_near(hit.normal, point(1.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn difference_flips_the_carved_normals() {
+        let r = Ray::new(point(5.0, 0.0, 0.0), point(-1.0, 0.0, 0.0));
+        let spans: Vec<Span> = hollow_cube().spans(&r);
+        assert_eq!(spans.len(), 2);
+        assert!((spans[0].entry.t - 4.0).abs() < 1e-9);
+        assert_near(spans[0].entry.normal, point(1.0, 0.0, 0.0));
+        // Leaving into the hollow, out of the solid and towards the middle
+        assert!((spans[0].exit.t - 4.5).abs() < 1e-9);
+        assert_near(spans[0].exit.normal, point(-1.0, 0.0, 0.0));
+        assert!((spans[1].entry.t - 5.5).abs() < 1e-9);
+        assert_near(spans[1].entry.normal, point(1.0, 0.0, 0.0));
+
+        // From inside the hollow the first hit is its wall, facing back in
+        let r = Ray::new(point(0.0, 0.0, 0.0), point(0.0, 1.0, 0.0));
+        let hit: Hit = hollow_cube().intersect(&r).unwrap();
+        assert!((hit.t - 0.5).abs() < 1e-9);
+        assert_near(hit.normal, point(0.0, -1.0, 0.0));
+    }
+
+    #[test]
+    fn intersection_of_disjoint_boxes_is_empty() {
+        let solid = Csg::new(Object::new_box(
+            &point(0.0, 0.0, 0.0),
+            &point(1.0, 1.0, 1.0),
+        ))
+        .intersection(Csg::new(Object::new_box(
+            &point(2.0, 0.0, 0.0),
+            &point(3.0, 1.0, 1.0),
+        )));
+        for origin in [point(-1.0, 0.5, 0.5), point(1.5, 0.5, 0.5)] {
+            let r = Ray::new(origin, point(1.0, 0.0, 0.0));
+            assert!(solid.intersect(&r).is_none());
+        }
+    }
+
+    #[test]
+    fn bite_out_of_a_cube() {
+        // The demo's cube, with a ball carved out of its corner
+        let corner: Vec3 = point(1.5, 0.3, -2.2);
+        let cube = Csg::new(Object::new_box(&point(0.9, -0.3, -2.8), &corner))
+            .difference(Csg::new(Object::new_sphere(&corner, &0.35)));
+
+        // Straight at the corner, past where it used to be, to the far side of the bite
+        let direction: Vec3 = point(-1.0, -1.0, -1.0);
+        let r = Ray::new(corner - direction, direction);
+        let hit: Hit = cube.intersect(&r).unwrap();
+        assert!((hit.t - (1.0 + 0.35 / libm::sqrt(3.0))).abs() < 1e-9);
+        assert!(((hit.point - corner).length() - 0.35).abs() < 1e-9);
+        assert_near(hit.normal, -direction.normalize());
+        assert!(Vec3::dot(&hit.normal, &direction) < 0.0);
+    }
+}
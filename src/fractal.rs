@@ -0,0 +1,196 @@
+use crate::vector::Vec3;
+use libm::{acos, atan2, cos, fabs, floor, log, pow, sin, sqrt};
+
+pub const BAILOUT: f64 = 2.0; // nothing further out than this is in the Mandelbulb
+
+// Distance estimate to the Mandelbulb of the given power
+pub fn mandelbulb(p: &Vec3, power: f64, iterations: usize) -> f64 {
+    let mut z: Vec3 = *p;
+    let mut dr: f64 = 1.0; // running derivative
+    let mut r: f64 = z.length();
+    for _ in 0..iterations {
+        if r > BAILOUT {
+            break;
+        }
+        // Raise z to the power in spherical coordinates, then add p back
+        let theta: f64 = acos((z.z / r).clamp(-1.0, 1.0)) * power;
+        let phi: f64 = atan2(z.y, z.x) * power;
+        dr = pow(r, power - 1.0) * power * dr + 1.0;
+        let zr: f64 = pow(r, power);
+        z = Vec3 {
+            x: zr * sin(theta) * cos(phi),
+            y: zr * sin(theta) * sin(phi),
+            z: zr * cos(theta),
+        } + *p;
+        r = z.length();
+    }
+    if r == 0.0 {
+        return 0.0;
+    }
+    0.5 * log(r) * r / dr
+}
+
+// Distance to the Menger sponge carved out of the cube from -1 to 1
+pub fn menger_sponge(p: &Vec3, iterations: usize) -> f64 {
+    let q: Vec3 = Vec3 {
+        x: fabs(p.x) - 1.0,
+        y: fabs(p.y) - 1.0,
+        z: fabs(p.z) - 1.0,
+    };
+    let outside: Vec3 = Vec3 {
+        x: q.x.max(0.0),
+        y: q.y.max(0.0),
+        z: q.z.max(0.0),
+    };
+    let mut distance: f64 = outside.length() + q.x.max(q.y).max(q.z).min(0.0);
+
+    // Each level cuts a cross shaped hole through every cell of the level above
+    let mut scale: f64 = 1.0;
+    for _ in 0..iterations {
+        let cell = |x: f64| {
+            let a: f64 = x * scale - 2.0 * floor(x * scale / 2.0) - 1.0;
+            fabs(1.0 - 3.0 * fabs(a))
+        };
+        let (x, y, z) = (cell(p.x), cell(p.y), cell(p.z));
+        scale *= 3.0;
+        let cross: f64 = (x.max(y).min(y.max(z)).min(z.max(x)) - 1.0) / scale;
+        distance = distance.max(cross);
+    }
+    distance
+}
+
+// Distance estimate to the Sierpinski tetrahedron with corners (1, 1, 1),
+// (1, -1, -1), (-1, 1, -1) and (-1, -1, 1)
+pub fn sierpinski_tetrahedron(p: &Vec3, iterations: usize) -> f64 {
+    let mut z: Vec3 = *p;
+    for _ in 0..iterations {
+        // Fold into the copy in the (1, 1, 1) corner, then blow it up to full size
+        if z.x + z.y < 0.0 {
+            (z.x, z.y) = (-z.y, -z.x);
+        }
+        if z.x + z.z < 0.0 {
+            (z.x, z.z) = (-z.z, -z.x);
+        }
+        if z.y + z.z < 0.0 {
+            (z.y, z.z) = (-z.z, -z.y);
+        }
+        z = z * 2.0
+            - Vec3 {
+                x: 1.0,
+                y: 1.0,
+                z: 1.0,
+            };
+    }
+    // The faces are opposite the corners
+    let face = |x: f64, y: f64, z: f64| (-x - y - z - 1.0) / sqrt(3.0);
+    let tetrahedron: f64 = face(z.x, z.y, z.z)
+        .max(face(z.x, -z.y, -z.z))
+        .max(face(-z.x, z.y, -z.z))
+        .max(face(-z.x, -z.y, z.z));
+    tetrahedron / pow(2.0, iterations as f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn point(x: f64, y: f64, z: f64) -> Vec3 {
+        Vec3 { x, y, z }
+    }
+
+    // Whether p stays bounded when iterated, the plain escape time test
+    fn in_mandelbulb(p: &Vec3, power: f64, iterations: usize) -> bool {
+        let mut z: Vec3 = *p;
+        for _ in 0..iterations {
+            let r: f64 = z.length();
+            if r > BAILOUT {
+                return false;
+            }
+            if r == 0.0 {
+                z = *p;
+                continue;
+            }
+            let theta: f64 = acos((z.z / r).clamp(-1.0, 1.0)) * power;
+            let phi: f64 = atan2(z.y, z.x) * power;
+            let zr: f64 = pow(r, power);
+            z = point(
+                zr * sin(theta) * cos(phi),
+                zr * sin(theta) * sin(phi),
+                zr * cos(theta),
+            ) + *p;
+        }
+        true
+    }
+
+    #[test]
+    fn menger_sponge_has_holes_through_the_middle() {
+        // Solid all through before anything is cut
+        assert!(menger_sponge(&point(0.0, 0.0, 0.0), 0) < 0.0);
+        for iterations in 1..5 {
+            for p in [
+                point(0.0, 0.0, 0.0),
+                point(0.0, 0.0, 0.9),
+                point(0.2, -0.9, 0.1),
+            ] {
+                assert!(menger_sponge(&p, iterations) > 0.0, "{:?}", p);
+            }
+            assert!(menger_sponge(&point(0.995, -0.995, 0.995), iterations) < 0.0);
+        }
+        assert!(fabs(menger_sponge(&point(3.0, 0.99, 0.99), 3) - 2.0) < 1e-9);
+    }
+
+    #[test]
+    fn mandelbulb_is_outside_past_the_bailout() {
+        for i in 0..50 {
+            let a: f64 = i as f64 * 0.7;
+            let b: f64 = i as f64 * 1.3;
+            let p: Vec3 = point(cos(a) * sin(b), sin(a) * sin(b), cos(b)) * (BAILOUT + 0.1);
+            assert!(mandelbulb(&p, 8.0, 10) > 0.0, "{:?}", p);
+        }
+    }
+
+    #[test]
+    fn mandelbulb_never_overshoots_along_a_ray() {
+        let step: f64 = 1e-3;
+        for i in 0..8 {
+            let a: f64 = i as f64 * 0.8;
+            let origin: Vec3 = point(cos(a) * 1.8, sin(a) * 1.8, 0.6 - 0.15 * i as f64);
+            let direction: Vec3 = (point(0.1, -0.05, 0.0) - origin).normalize();
+
+            // First point on the ray that belongs to the set
+            let surface: f64 = (0..3000)
+                .map(|n| n as f64 * step)
+                .find(|&t| in_mandelbulb(&(origin + direction * t), 8.0, 10))
+                .unwrap();
+            let mut t: f64 = 0.0;
+            while t < surface {
+                let estimate: f64 = mandelbulb(&(origin + direction * t), 8.0, 10);
+                assert!(
+                    estimate <= surface - t + step,
+                    "{} from {:?}, but the surface is {} away",
+                    estimate,
+                    origin + direction * t,
+                    surface - t
+                );
+                t += step * 10.0;
+            }
+        }
+    }
+
+    #[test]
+    fn sierpinski_corners_are_on_the_surface() {
+        for iterations in 0..6 {
+            for corner in [
+                point(1.0, 1.0, 1.0),
+                point(1.0, -1.0, -1.0),
+                point(-1.0, 1.0, -1.0),
+                point(-1.0, -1.0, 1.0),
+            ] {
+                assert!(fabs(sierpinski_tetrahedron(&corner, iterations)) < 1e-9);
+            }
+        }
+        // The middle is carved out from the first level on
+        assert!(sierpinski_tetrahedron(&point(0.0, 0.0, 0.0), 0) < 0.0);
+        assert!(sierpinski_tetrahedron(&point(0.0, 0.0, 0.0), 1) > 0.0);
+    }
+}
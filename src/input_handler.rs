@@ -1,23 +1,47 @@
+#[cfg(test)]
+use crate::clock::Clock;
 use input::event::keyboard::KeyboardEventTrait;
 use input::{Libinput, LibinputInterface};
-use libc::{O_RDONLY, O_RDWR, O_WRONLY};
+use libc::{read, O_ACCMODE, O_RDONLY, O_RDWR, O_WRONLY, STDIN_FILENO};
 use std::fs::{File, OpenOptions};
 use std::os::unix::{fs::OpenOptionsExt, io::OwnedFd};
 use std::path::Path;
 
 struct Interface;
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub enum InputEvent {
     MouseMove(f64, f64),
+    MouseDrag(MouseButton, (f64, f64), (f64, f64)), // button, from, to
+    Scroll(f64),                                    // positive scrolls up
     Keyboard(char),
+    Arrow(Arrow),
     None,
 }
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Arrow {
+    Up,
+    Down,
+    Left,
+    Right,
+}
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum MouseButton {
+    Left,
+    Middle,
+    Right,
+}
+
+// Reads key presses and mouse reports from a terminal in raw mode
+pub struct TerminalInput {
+    mouse: Option<(f64, f64)>,
+}
 
 impl LibinputInterface for Interface {
     fn open_restricted(&mut self, path: &Path, flags: i32) -> Result<OwnedFd, i32> {
         OpenOptions::new()
             .custom_flags(flags)
-            .read((flags & O_RDONLY != 0) | (flags & O_RDWR != 0))
-            .write((flags & O_WRONLY != 0) | (flags & O_RDWR != 0))
+            .read((flags & O_ACCMODE == O_RDONLY) | (flags & O_ACCMODE == O_RDWR))
+            .write((flags & O_ACCMODE == O_WRONLY) | (flags & O_ACCMODE == O_RDWR))
             .open(path)
             .map(|file| file.into())
             .map_err(|err| err.raw_os_error().unwrap())
@@ -57,3 +81,144 @@ impl InputEvent {
         }
     }
 }
+
+impl TerminalInput {
+    pub fn new() -> TerminalInput {
+        TerminalInput { mouse: None }
+    }
+
+    // Drains everything currently buffered on stdin without blocking
+    pub fn poll(&mut self) -> Vec<InputEvent> {
+        let mut bytes: Vec<u8> = Vec::new();
+        let mut buf = [0u8; 1024];
+        loop {
+            let n = unsafe {
+                read(
+                    STDIN_FILENO,
+                    buf.as_mut_ptr() as *mut libc::c_void,
+                    buf.len(),
+                )
+            };
+            if n <= 0 {
+                break;
+            }
+            bytes.extend_from_slice(&buf[..n as usize]);
+        }
+        self.parse(&bytes)
+    }
+
+    fn parse(&mut self, bytes: &[u8]) -> Vec<InputEvent> {
+        let mut events: Vec<InputEvent> = Vec::new();
+        let mut i = 0;
+        while i < bytes.len() {
+            if bytes[i] != 0x1B || bytes.get(i + 1) != Some(&b'[') {
+                events.push(InputEvent::Keyboard(bytes[i] as char));
+                i += 1;
+                continue;
+            }
+            match bytes.get(i + 2) {
+                Some(b'A') => events.push(InputEvent::Arrow(Arrow::Up)),
+                Some(b'B') => events.push(InputEvent::Arrow(Arrow::Down)),
+                Some(b'C') => events.push(InputEvent::Arrow(Arrow::Right)),
+                Some(b'D') => events.push(InputEvent::Arrow(Arrow::Left)),
+                Some(b'<') => {
+                    // SGR mouse report: ESC [ < button ; column ; row (M | m)
+                    let body = &bytes[i + 3..];
+                    match body.iter().position(|b| *b == b'M' || *b == b'm') {
+                        Some(len) => {
+                            events.extend(self.mouse_event(&body[..len]));
+                            i += 3 + len + 1;
+                        }
+                        None => i = bytes.len(),
+                    }
+                    continue;
+                }
+                _ => {}
+            }
+            i += 3;
+        }
+        events
+    }
+
+    fn mouse_event(&mut self, body: &[u8]) -> Vec<InputEvent> {
+        let fields: Vec<f64> = match std::str::from_utf8(body) {
+            Ok(body) => body
+                .split(';')
+                .filter_map(|field| field.parse::<f64>().ok())
+                .collect(),
+            Err(_) => return Vec::new(),
+        };
+        if fields.len() != 3 {
+            return Vec::new();
+        }
+        let (code, x, y) = (fields[0] as u32, fields[1], fields[2]);
+
+        // Wheel notches carry bit 64, motion reports carry bit 32
+        if code & 64 != 0 {
+            let notch = if code & 1 == 0 { 1.0 } else { -1.0 };
+            return vec![InputEvent::Scroll(notch)];
+        }
+        let button = match code & 3 {
+            0 => Some(MouseButton::Left),
+            1 => Some(MouseButton::Middle),
+            2 => Some(MouseButton::Right),
+            _ => None,
+        };
+
+        let mut events: Vec<InputEvent> = Vec::new();
+        if let Some((px, py)) = self.mouse.replace((x, y)) {
+            if code & 32 != 0 {
+                events.push(InputEvent::MouseMove(x - px, y - py));
+                if let Some(button) = button {
+                    events.push(InputEvent::MouseDrag(button, (px, py), (x, y)));
+                }
+            }
+        }
+        events
+    }
+}
+
+// Timestamped input captured from a session, to be fed back on any Clock
+#[cfg(test)]
+pub struct InputReplay {
+    events: Vec<(f64, InputEvent)>,
+    cursor: usize,
+}
+
+#[cfg(test)]
+impl InputReplay {
+    pub fn new() -> InputReplay {
+        InputReplay {
+            events: Vec::new(),
+            cursor: 0,
+        }
+    }
+    pub fn from_events(events: Vec<(f64, InputEvent)>) -> InputReplay {
+        InputReplay { events, cursor: 0 }
+    }
+
+    pub fn record<C: Clock>(&mut self, clock: &C, events: &[InputEvent]) {
+        let now = clock.now();
+        self.events.extend(events.iter().map(|event| (now, *event)));
+    }
+
+    // Every recorded event whose timestamp has been reached and not yet returned
+    pub fn poll<C: Clock>(&mut self, clock: &C) -> Vec<InputEvent> {
+        let now = clock.now();
+        let start = self.cursor;
+        while self.cursor < self.events.len() && self.events[self.cursor].0 <= now {
+            self.cursor += 1;
+        }
+        self.events[start..self.cursor]
+            .iter()
+            .map(|(_, event)| *event)
+            .collect()
+    }
+
+    pub fn rewind(&mut self) {
+        self.cursor = 0;
+    }
+    pub fn events(&self) -> &[(f64, InputEvent)] {
+        &self.events
+    }
+}
@@ -0,0 +1,220 @@
+use crate::aabb::Aabb;
+use crate::bvh::Bvh;
+use crate::csg::Csg;
+use crate::matrix::Mat4;
+use crate::mesh::Mesh;
+use crate::object::{Hit, Object, ObjectTrait};
+use crate::ray::Ray;
+use crate::sdf::SdfObject;
+use crate::transform::Transform;
+use crate::vector::Vec3;
+use std::rc::Rc;
+use std::slice;
+
+// Anything that can be placed in the scene any number of times, in its own
+// object space
+#[derive(Debug, Clone)]
+pub enum Geometry {
+    Object(Object),
+    Mesh(Mesh, Bvh), // with a tree of its own, so copies of big meshes stay cheap
+    Sdf(SdfObject),
+    Csg(Csg),
+}
+
+impl Geometry {
+    pub fn new_object(object: Object) -> Self {
+        Geometry::Object(object)
+    }
+    pub fn new_mesh(mesh: Mesh) -> Self {
+        let bvh: Bvh = Bvh::build(&[], slice::from_ref(&mesh), &[]);
+        Geometry::Mesh(mesh, bvh)
+    }
+    pub fn new_sdf(sdf: SdfObject) -> Self {
+        Geometry::Sdf(sdf)
+    }
+    pub fn new_csg(csg: Csg) -> Self {
+        Geometry::Csg(csg)
+    }
+
+    pub fn intersect(&self, r: &Ray) -> Option<Hit> {
+        match self {
+            Geometry::Object(object) => object.intersect(r),
+            Geometry::Mesh(mesh, bvh) => bvh.intersect(&[], slice::from_ref(mesh), &[], r),
+            Geometry::Sdf(sdf) => sdf.intersect(r),
+            Geometry::Csg(csg) => csg.intersect(r),
+        }
+    }
+
+    // None if unbounded
+    pub fn bounds(&self) -> Option<Aabb> {
+        match self {
+            Geometry::Object(object) => object.bounds(),
+            Geometry::Mesh(mesh, _) => Some(mesh.bounds()),
+            Geometry::Sdf(sdf) => sdf.bounds(),
+            Geometry::Csg(csg) => csg.bounds(),
+        }
+    }
+}
+
+// Shared geometry placed in the world by a matrix
+#[derive(Debug, Clone)]
+pub struct Instance {
+    geometry: Rc<Geometry>,
+    matrix: Mat4,
+    inverse: Mat4,
+    bounds: Option<Aabb>,
+}
+
+impl Instance {
+    pub fn from_matrices(geometry: Rc<Geometry>, matrix: Mat4, inverse: Mat4) -> Instance {
+        let bounds: Option<Aabb> = geometry
+            .bounds()
+            .map(|bounds| bounds.transformed(|corner| matrix.transform_point(corner)));
+        Instance {
+            geometry,
+            matrix,
+            inverse,
+            bounds,
+        }
+    }
+
+    pub fn geometry(&self) -> &Rc<Geometry> {
+        &self.geometry
+    }
+    pub fn bounds(&self) -> Option<Aabb> {
+        self.bounds
+    }
+
+    // Applies another transform on top of the instance's
+    fn then(&self, transform: &Transform) -> Instance {
+        Instance::from_matrices(
+            self.geometry.clone(),
+            transform.matrix() * self.matrix,
+            self.inverse * transform.inverse(),
+        )
+    }
+}
+
+impl ObjectTrait for Instance {
+    // The ray is taken into object space without normalizing its direction, so
+    // the hit's t holds in world space too
+    fn intersect(&self, r: &Ray) -> Option<Hit> {
+        if self
+            .bounds
+            .is_some_and(|bounds| bounds.hit(r, f64::INFINITY).is_none())
+        {
+            return None;
+        }
+        let local: Ray = Ray::new(
+            self.inverse.transform_point(&r.origin()),
+            self.inverse.transform_vector(&r.direction()),
+        );
+        let hit: Hit = self.geometry.intersect(&local)?;
+        // Normals go through the inverse transpose, which keeps them at right
+        // angles to the surface under non-uniform scales
+        let normal: Vec3 = self.inverse.transpose().transform_vector(&hit.normal);
+        Some(Hit {
+            point: r.origin() + r.direction() * hit.t,
+            normal: normal.normalize(),
+            ..hit
+        })
+    }
+    fn move_to(&self, v: Vec3) -> Self {
+        self.then(&Transform::identity().translate(v))
+    }
+    fn rotate_around_center(&self, euler: Vec3) -> Self {
+        match self.bounds {
+            Some(bounds) => self.rotate_around_point(euler, bounds.center()),
+            None => self.clone(),
+        }
+    }
+    fn rotate_around_point(&self, euler: Vec3, point: Vec3) -> Self {
+        let rotation: Transform = Transform::identity().rotate(euler);
+        self.then(&Transform::identity().translate(-point))
+            .then(&rotation)
+            .then(&Transform::identity().translate(point))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rotation::Quaternion;
+    use libm::sqrt;
+
+    #[test]
+    fn non_uniform_scale() {
+        // A unit ball stretched to twice as wide, 5 in front of the origin
+        let ball = Rc::new(Geometry::new_object(Object::new_sphere(
+            &Vec3 {
+                x: 0.0,
+                y: 0.0,
+                z: 0.0,
+            },
+            &1.0,
+        )));
+        let transform = Transform::new(
+            Vec3 {
+                x: 0.0,
+                y: 0.0,
+                z: -5.0,
+            },
+            Quaternion::new(1.0, 0.0, 0.0, 0.0),
+            Vec3 {
+                x: 2.0,
+                y: 1.0,
+                z: 1.0,
+            },
+        );
+        let instance = Instance::from_matrices(ball, transform.matrix(), transform.inverse());
+
+        // t is in world units, even with a direction that isn't unit length
+        let r = Ray::new(
+            Vec3 {
+                x: 0.0,
+                y: 0.0,
+                z: 0.0,
+            },
+            Vec3 {
+                x: 0.0,
+                y: 0.0,
+                z: -2.0,
+            },
+        );
+        let hit: Hit = instance.intersect(&r).unwrap();
+        assert!((hit.t - 2.0).abs() < 1e-9);
+        assert!(hit.point.approx_eq(
+            &Vec3 {
+                x: 0.0,
+                y: 0.0,
+                z: -4.0
+            },
+            1e-9
+        ));
+
+        // Coming down onto x^2/4 + y^2 = 1, where the surface's normal goes as
+        // (x/4, y). Stretching the unit ball's normal instead would tip it the other way.
+        let r = Ray::new(
+            Vec3 {
+                x: sqrt(2.0),
+                y: 5.0,
+                z: -5.0,
+            },
+            Vec3 {
+                x: 0.0,
+                y: -1.0,
+                z: 0.0,
+            },
+        );
+        let hit: Hit = instance.intersect(&r).unwrap();
+        assert!((hit.t - (5.0 - 1.0 / sqrt(2.0))).abs() < 1e-9);
+        assert!(hit.normal.approx_eq(
+            &Vec3 {
+                x: 1.0 / sqrt(5.0),
+                y: 2.0 / sqrt(5.0),
+                z: 0.0
+            },
+            1e-9
+        ));
+    }
+}
@@ -0,0 +1,90 @@
+use crate::scene::Scene;
+use crate::vector::Vec3;
+use std::fmt;
+use std::io;
+use std::path::Path;
+
+pub mod gltf;
+mod json;
+pub mod obj;
+pub mod ply;
+pub mod stl;
+
+#[derive(Debug)]
+pub enum LoadError {
+    Io(io::Error),
+    Parse { line: usize, message: String },
+    Invalid(String), // malformed binary data, which has no lines to point at
+    Unsupported(String),
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LoadError::Io(err) => write!(f, "{}", err),
+            LoadError::Parse { line, message } => write!(f, "line {}: {}", line, message),
+            LoadError::Invalid(message) => write!(f, "{}", message),
+            LoadError::Unsupported(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl From<io::Error> for LoadError {
+    fn from(err: io::Error) -> LoadError {
+        LoadError::Io(err)
+    }
+}
+
+// Loads a model file into a scene, picking the format from the extension
+pub fn load(path: &Path) -> Result<Scene, LoadError> {
+    let extension: String = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .unwrap_or("")
+        .to_ascii_lowercase();
+
+    let mut scene = Scene::new();
+    match extension.as_str() {
+        "obj" => {
+            for mesh in obj::load(path)? {
+                scene.add_mesh(mesh);
+            }
+        }
+        "gltf" | "glb" => scene = gltf::load(path)?.to_scene(),
+        "ply" => {
+            scene.add_mesh(ply::load(path)?);
+        }
+        "stl" => {
+            scene.add_mesh(stl::load(path)?);
+        }
+        _ => {
+            return Err(LoadError::Unsupported(format!(
+                "unsupported file type '.{}'",
+                extension
+            )))
+        }
+    }
+    Ok(scene)
+}
+
+fn parse_error(line: usize, message: &str) -> LoadError {
+    LoadError::Parse {
+        line,
+        message: message.to_string(),
+    }
+}
+
+fn parse_number(token: Option<&&str>, line: usize) -> Result<f64, LoadError> {
+    let token: &str = token.ok_or_else(|| parse_error(line, "missing number"))?;
+    token
+        .parse::<f64>()
+        .map_err(|_| parse_error(line, &format!("invalid number '{}'", token)))
+}
+
+fn parse_vec3(tokens: &[&str], line: usize) -> Result<Vec3, LoadError> {
+    Ok(Vec3 {
+        x: parse_number(tokens.first(), line)?,
+        y: parse_number(tokens.get(1), line)?,
+        z: parse_number(tokens.get(2), line)?,
+    })
+}
@@ -0,0 +1,945 @@
+use super::json::Json;
+use super::LoadError;
+use crate::instance::Geometry;
+use crate::matrix::{Mat3, Mat4};
+use crate::mesh::Mesh;
+use crate::rotation::Quaternion;
+use crate::scene::{Scene, View};
+use crate::transform::Transform;
+use crate::vector::Vec3;
+use std::fs;
+use std::path::Path;
+use std::rc::Rc;
+
+const GLB_MAGIC: &[u8] = b"glTF";
+const CHUNK_JSON: u32 = 0x4E4F534A;
+const CHUNK_BIN: u32 = 0x004E4942;
+
+// A node of the glTF hierarchy, kept as the file describes it
+#[derive(Debug, Clone)]
+pub struct Node {
+    pub name: Option<String>,
+    pub translation: Vec3,
+    pub rotation: Quaternion,
+    pub scale: Vec3,
+    pub mesh: Option<usize>,
+    pub camera: Option<usize>,
+    pub children: Vec<usize>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Camera {
+    pub name: Option<String>,
+    pub yfov: Option<f64>, // vertical field of view in radians, None for orthographic cameras
+}
+
+#[derive(Debug, Clone)]
+pub struct Gltf {
+    pub meshes: Vec<Vec<Mesh>>, // one mesh per primitive
+    pub cameras: Vec<Camera>,
+    pub nodes: Vec<Node>,
+    pub roots: Vec<usize>, // nodes of the default scene
+}
+
+impl Gltf {
+    // Turns the hierarchy into scene nodes, each keeping its own transform.
+    // Nodes that use the same mesh share its geometry, and the first camera
+    // found becomes the scene's view.
+    pub fn to_scene(&self) -> Scene {
+        let mut scene = Scene::new();
+        let geometries: Vec<Vec<Rc<Geometry>>> = self
+            .meshes
+            .iter()
+            .map(|primitives| {
+                primitives
+                    .iter()
+                    .map(|primitive| Rc::new(Geometry::new_mesh(primitive.clone())))
+                    .collect()
+            })
+            .collect();
+        let mut visited: Vec<bool> = vec![false; self.nodes.len()];
+        for root in &self.roots {
+            self.visit(
+                *root,
+                None,
+                Mat4::identity(),
+                Quaternion::new(1.0, 0.0, 0.0, 0.0),
+                &geometries,
+                &mut scene,
+                &mut visited,
+            );
+        }
+        scene.update_instances();
+        scene
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn visit(
+        &self,
+        index: usize,
+        parent: Option<usize>,
+        parent_world: Mat4,
+        parent_rotation: Quaternion,
+        geometries: &[Vec<Rc<Geometry>>],
+        scene: &mut Scene,
+        visited: &mut [bool],
+    ) {
+        // A node can only have one parent, so seeing it twice means the file has a cycle
+        if visited[index] {
+            return;
+        }
+        visited[index] = true;
+
+        let node: &Node = &self.nodes[index];
+        let transform = Transform::new(node.translation, node.rotation, node.scale);
+        let world: Mat4 = parent_world * transform.matrix();
+        let rotation: Quaternion = (parent_rotation * node.rotation).normalize();
+
+        // A mesh of one primitive sits on the node itself, and any more hang
+        // under it untransformed
+        let primitives: &[Rc<Geometry>] = match node.mesh {
+            Some(mesh) => &geometries[mesh],
+            None => &[],
+        };
+        let geometry: Option<Rc<Geometry>> = match primitives {
+            [primitive] => Some(primitive.clone()),
+            _ => None,
+        };
+        let id: usize = scene.add_node(parent, transform, geometry);
+        scene.nodes[id].name = node.name.clone();
+        if primitives.len() > 1 {
+            for primitive in primitives {
+                scene.add_node(Some(id), Transform::identity(), Some(primitive.clone()));
+            }
+        }
+
+        if let Some(camera) = node.camera {
+            if scene.view.is_none() {
+                scene.view = Some(View {
+                    position: world.translation(),
+                    orientation: rotation,
+                    yfov: self.cameras[camera].yfov,
+                });
+            }
+        }
+        for child in &node.children {
+            self.visit(
+                *child,
+                Some(id),
+                world,
+                rotation,
+                geometries,
+                scene,
+                visited,
+            );
+        }
+    }
+}
+
+// Reads a .gltf or .glb file, along with any external buffers next to it
+pub fn load(path: &Path) -> Result<Gltf, LoadError> {
+    let bytes: Vec<u8> = fs::read(path)?;
+    let directory: &Path = path.parent().unwrap_or(Path::new(""));
+    let read_uri = |uri: &str| fs::read(directory.join(uri)).ok();
+
+    if bytes.starts_with(GLB_MAGIC) {
+        parse_glb(&bytes, read_uri)
+    } else {
+        let source: &str = std::str::from_utf8(&bytes)
+            .map_err(|_| LoadError::Invalid("glTF file is not text".to_string()))?;
+        parse(source, None, read_uri)
+    }
+}
+
+// Splits a binary .glb container into its JSON and BIN chunks
+pub fn parse_glb(
+    bytes: &[u8],
+    read_uri: impl Fn(&str) -> Option<Vec<u8>>,
+) -> Result<Gltf, LoadError> {
+    let truncated = || LoadError::Invalid("truncated GLB file".to_string());
+    let word = |offset: usize| -> Option<u32> {
+        Some(u32::from_le_bytes(
+            bytes.get(offset..offset + 4)?.try_into().ok()?,
+        ))
+    };
+    if word(4).ok_or_else(truncated)? != 2 {
+        return Err(LoadError::Unsupported(
+            "only version 2 GLB files are supported".to_string(),
+        ));
+    }
+
+    let length: usize = (word(8).ok_or_else(truncated)? as usize).min(bytes.len());
+    let mut json: Option<&str> = None;
+    let mut binary: Option<&[u8]> = None;
+    let mut offset: usize = 12;
+    while offset + 8 <= length {
+        let chunk_length: usize = word(offset).ok_or_else(truncated)? as usize;
+        let chunk_type: u32 = word(offset + 4).ok_or_else(truncated)?;
+        let data: &[u8] = bytes
+            .get(offset + 8..offset + 8 + chunk_length)
+            .ok_or_else(truncated)?;
+        match chunk_type {
+            CHUNK_JSON if json.is_none() => {
+                json =
+                    Some(std::str::from_utf8(data).map_err(|_| {
+                        LoadError::Invalid("GLB JSON chunk is not text".to_string())
+                    })?);
+            }
+            CHUNK_BIN if binary.is_none() => binary = Some(data),
+            // Extension chunks, which readers are meant to skip
+            _ => {}
+        }
+        offset += 8 + chunk_length;
+    }
+
+    let json: &str =
+        json.ok_or_else(|| LoadError::Invalid("GLB file has no JSON chunk".to_string()))?;
+    parse(json, binary, read_uri)
+}
+
+// Parses a glTF document. `binary` is the BIN chunk of a .glb file, and `read_uri`
+// returns the contents of an external buffer.
+pub fn parse(
+    source: &str,
+    binary: Option<&[u8]>,
+    read_uri: impl Fn(&str) -> Option<Vec<u8>>,
+) -> Result<Gltf, LoadError> {
+    let document: Json = Json::parse(source)?;
+    let version: &str = document
+        .get("asset")
+        .and_then(|asset| asset.get("version"))
+        .and_then(|version| version.as_str())
+        .unwrap_or("");
+    if !version.starts_with("2.") {
+        return Err(LoadError::Unsupported(format!(
+            "unsupported glTF version '{}'",
+            version
+        )));
+    }
+
+    let mut buffers: Vec<Vec<u8>> = Vec::new();
+    for (index, buffer) in list(&document, "buffers").iter().enumerate() {
+        let data: Vec<u8> = match buffer.get("uri").and_then(|uri| uri.as_str()) {
+            Some(uri) if uri.starts_with("data:") => {
+                let (header, data) = uri.split_once(',').unwrap_or((uri, ""));
+                if !header.ends_with(";base64") {
+                    return Err(LoadError::Unsupported(format!(
+                        "buffer {} is a data URI that isn't base64",
+                        index
+                    )));
+                }
+                decode_base64(data)
+                    .ok_or_else(|| invalid(format!("buffer {} has invalid base64 data", index)))?
+            }
+            Some(uri) => {
+                read_uri(uri).ok_or_else(|| invalid(format!("can't read buffer '{}'", uri)))?
+            }
+            None => binary
+                .ok_or_else(|| invalid(format!("buffer {} has no data", index)))?
+                .to_vec(),
+        };
+        buffers.push(data);
+    }
+
+    let mut meshes: Vec<Vec<Mesh>> = Vec::new();
+    for mesh in list(&document, "meshes") {
+        let mut primitives: Vec<Mesh> = Vec::new();
+        for primitive in list(mesh, "primitives") {
+            if let Some(mut primitive) = read_primitive(&document, &buffers, primitive)? {
+                if let Some(name) = mesh.get("name").and_then(|name| name.as_str()) {
+                    primitive.set_name(name);
+                }
+                primitives.push(primitive);
+            }
+        }
+        meshes.push(primitives);
+    }
+
+    let cameras: Vec<Camera> = list(&document, "cameras")
+        .iter()
+        .map(|camera| Camera {
+            name: camera
+                .get("name")
+                .and_then(|name| name.as_str())
+                .map(|name| name.to_string()),
+            yfov: camera
+                .get("perspective")
+                .and_then(|perspective| perspective.get("yfov"))
+                .and_then(|yfov| yfov.as_f64()),
+        })
+        .collect();
+
+    let node_count: usize = list(&document, "nodes").len();
+    let mut nodes: Vec<Node> = Vec::with_capacity(node_count);
+    for (index, node) in list(&document, "nodes").iter().enumerate() {
+        nodes.push(read_node(
+            node,
+            index,
+            node_count,
+            meshes.len(),
+            cameras.len(),
+        )?);
+    }
+
+    // Without a scene list, every node that isn't somebody's child is a root
+    let scene: usize = document
+        .get("scene")
+        .and_then(|scene| scene.as_usize())
+        .unwrap_or(0);
+    let roots: Vec<usize> = match list(&document, "scenes").get(scene) {
+        Some(scene) => list(scene, "nodes")
+            .iter()
+            .map(|node| {
+                node.as_usize()
+                    .filter(|node| *node < node_count)
+                    .ok_or_else(|| invalid("scene refers to a missing node".to_string()))
+            })
+            .collect::<Result<Vec<usize>, LoadError>>()?,
+        None => (0..node_count)
+            .filter(|index| !nodes.iter().any(|node| node.children.contains(index)))
+            .collect(),
+    };
+
+    Ok(Gltf {
+        meshes,
+        cameras,
+        nodes,
+        roots,
+    })
+}
+
+fn invalid(message: String) -> LoadError {
+    LoadError::Invalid(message)
+}
+
+// An optional top level array such as "meshes", empty when it's missing
+fn list<'a>(json: &'a Json, key: &str) -> &'a [Json] {
+    json.get(key).map(|value| value.as_array()).unwrap_or(&[])
+}
+
+fn numbers<const N: usize>(json: Option<&Json>, default: [f64; N]) -> [f64; N] {
+    let mut values: [f64; N] = default;
+    if let Some(json) = json {
+        for (value, number) in values.iter_mut().zip(json.as_array()) {
+            if let Some(number) = number.as_f64() {
+                *value = number;
+            }
+        }
+    }
+    values
+}
+
+fn read_node(
+    node: &Json,
+    index: usize,
+    node_count: usize,
+    mesh_count: usize,
+    camera_count: usize,
+) -> Result<Node, LoadError> {
+    let reference = |key: &str, count: usize| -> Result<Option<usize>, LoadError> {
+        match node.get(key) {
+            Some(value) => match value.as_usize().filter(|value| *value < count) {
+                Some(value) => Ok(Some(value)),
+                None => Err(invalid(format!("node {} has an invalid {}", index, key))),
+            },
+            None => Ok(None),
+        }
+    };
+
+    let mut children: Vec<usize> = Vec::new();
+    for child in list(node, "children") {
+        match child.as_usize().filter(|child| *child < node_count) {
+            Some(child) => children.push(child),
+            None => return Err(invalid(format!("node {} has an invalid child", index))),
+        }
+    }
+
+    let (translation, rotation, scale) = match node.get("matrix") {
+        Some(matrix) => decompose(&numbers(Some(matrix), [0.0; 16])),
+        None => {
+            let [tx, ty, tz] = numbers(node.get("translation"), [0.0; 3]);
+            let [qx, qy, qz, qw] = numbers(node.get("rotation"), [0.0, 0.0, 0.0, 1.0]);
+            let [sx, sy, sz] = numbers(node.get("scale"), [1.0; 3]);
+            (
+                Vec3 {
+                    x: tx,
+                    y: ty,
+                    z: tz,
+                },
+                Quaternion::new(qw, qx, qy, qz),
+                Vec3 {
+                    x: sx,
+                    y: sy,
+                    z: sz,
+                },
+            )
+        }
+    };
+
+    Ok(Node {
+        name: node
+            .get("name")
+            .and_then(|name| name.as_str())
+            .map(|name| name.to_string()),
+        translation,
+        rotation,
+        scale,
+        mesh: reference("mesh", mesh_count)?,
+        camera: reference("camera", camera_count)?,
+        children,
+    })
+}
+
+// Splits a column-major 4x4 node matrix back into translation, rotation and scale
+fn decompose(m: &[f64; 16]) -> (Vec3, Quaternion, Vec3) {
+    let mut columns: [Vec3; 3] = [0, 4, 8].map(|i| Vec3 {
+        x: m[i],
+        y: m[i + 1],
+        z: m[i + 2],
+    });
+    let mut scale: [f64; 3] = columns.map(|column| column.length());
+    // A mirrored matrix can't be a pure rotation, so put the flip into the scale
+    if Vec3::dot(&columns[0], &Vec3::cross(&columns[1], &columns[2])) < 0.0 {
+        scale[0] = -scale[0];
+    }
+    for (column, scale) in columns.iter_mut().zip(scale) {
+        if scale != 0.0 {
+            *column = *column / scale;
+        }
+    }
+
+    let rotation: Quaternion =
+        Mat3::from_columns(&columns[0], &columns[1], &columns[2]).to_quaternion();
+
+    (
+        Vec3 {
+            x: m[12],
+            y: m[13],
+            z: m[14],
+        },
+        rotation,
+        Vec3 {
+            x: scale[0],
+            y: scale[1],
+            z: scale[2],
+        },
+    )
+}
+
+fn decode_base64(text: &str) -> Option<Vec<u8>> {
+    let mut bytes: Vec<u8> = Vec::with_capacity(text.len() * 3 / 4);
+    let mut buffer: u32 = 0;
+    let mut bits: u32 = 0;
+    for character in text.bytes() {
+        let value: u8 = match character {
+            b'A'..=b'Z' => character - b'A',
+            b'a'..=b'z' => character - b'a' + 26,
+            b'0'..=b'9' => character - b'0' + 52,
+            b'+' | b'-' => 62,
+            b'/' | b'_' => 63,
+            b'=' => break,
+            b' ' | b'\t' | b'\r' | b'\n' => continue,
+            _ => return None,
+        };
+        buffer = ((buffer << 6) | value as u32) & 0xFFFF;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            bytes.push((buffer >> bits) as u8);
+        }
+    }
+    Some(bytes)
+}
+
+// Number of components for an accessor type
+fn components(kind: &str) -> Option<usize> {
+    match kind {
+        "SCALAR" => Some(1),
+        "VEC2" => Some(2),
+        "VEC3" => Some(3),
+        "VEC4" | "MAT2" => Some(4),
+        "MAT3" => Some(9),
+        "MAT4" => Some(16),
+        _ => None,
+    }
+}
+
+// One component of an accessor, mapped into -1 to 1 or 0 to 1 if it's normalized
+fn component(data: &[u8], offset: usize, component_type: usize, normalized: bool) -> Option<f64> {
+    let bytes = |size: usize| data.get(offset..offset.checked_add(size)?);
+    let (value, range): (f64, f64) = match component_type {
+        5120 => (bytes(1)?[0] as i8 as f64, 127.0),
+        5121 => (bytes(1)?[0] as f64, 255.0),
+        5122 => (
+            i16::from_le_bytes(bytes(2)?.try_into().ok()?) as f64,
+            32767.0,
+        ),
+        5123 => (
+            u16::from_le_bytes(bytes(2)?.try_into().ok()?) as f64,
+            65535.0,
+        ),
+        5125 => (
+            u32::from_le_bytes(bytes(4)?.try_into().ok()?) as f64,
+            u32::MAX as f64,
+        ),
+        5126 => (f32::from_le_bytes(bytes(4)?.try_into().ok()?) as f64, 1.0),
+        _ => return None,
+    };
+    if normalized && component_type != 5126 {
+        Some((value / range).max(-1.0))
+    } else {
+        Some(value)
+    }
+}
+
+fn component_size(component_type: usize) -> Option<usize> {
+    match component_type {
+        5120 | 5121 => Some(1),
+        5122 | 5123 => Some(2),
+        5125 | 5126 => Some(4),
+        _ => None,
+    }
+}
+
+// Reads an accessor into one Vec per element
+fn read_accessor(
+    document: &Json,
+    buffers: &[Vec<u8>],
+    index: usize,
+) -> Result<Vec<Vec<f64>>, LoadError> {
+    let accessor: &Json = list(document, "accessors")
+        .get(index)
+        .ok_or_else(|| invalid(format!("accessor {} does not exist", index)))?;
+    let broken = |what: &str| invalid(format!("accessor {} has {}", index, what));
+
+    if accessor.get("sparse").is_some() {
+        return Err(LoadError::Unsupported(
+            "sparse accessors are not supported".to_string(),
+        ));
+    }
+    let count: usize = accessor
+        .get("count")
+        .and_then(|count| count.as_usize())
+        .ok_or_else(|| broken("no count"))?;
+    let component_type: usize = accessor
+        .get("componentType")
+        .and_then(|kind| kind.as_usize())
+        .ok_or_else(|| broken("no component type"))?;
+    let size: usize =
+        component_size(component_type).ok_or_else(|| broken("an unknown component type"))?;
+    let width: usize = accessor
+        .get("type")
+        .and_then(|kind| kind.as_str())
+        .and_then(components)
+        .ok_or_else(|| broken("an unknown type"))?;
+    let element_size: usize = width * size;
+    let normalized: bool = accessor
+        .get("normalized")
+        .and_then(|normalized| normalized.as_bool())
+        .unwrap_or(false);
+    let offset: usize = accessor
+        .get("byteOffset")
+        .and_then(|offset| offset.as_usize())
+        .unwrap_or(0);
+
+    // Accessors without a buffer view are all zeros. Their count has no data
+    // to be checked against, so hold it to what the file's buffers could fit.
+    let view: &Json = match accessor.get("bufferView").and_then(|view| view.as_usize()) {
+        Some(view) => list(document, "bufferViews")
+            .get(view)
+            .ok_or_else(|| broken("a missing buffer view"))?,
+        None => {
+            let bytes: usize = buffers.iter().map(|buffer| buffer.len()).sum();
+            if count > bytes / element_size {
+                return Err(broken("more elements than the file's buffers could hold"));
+            }
+            return Ok(vec![vec![0.0; width]; count]);
+        }
+    };
+    let buffer: &Vec<u8> = view
+        .get("buffer")
+        .and_then(|buffer| buffer.as_usize())
+        .and_then(|buffer| buffers.get(buffer))
+        .ok_or_else(|| broken("a buffer view without a buffer"))?;
+    let view_offset: usize = view
+        .get("byteOffset")
+        .and_then(|offset| offset.as_usize())
+        .unwrap_or(0);
+    let view_length: usize = view
+        .get("byteLength")
+        .and_then(|length| length.as_usize())
+        .ok_or_else(|| broken("a buffer view without a length"))?;
+    let stride: usize = view
+        .get("byteStride")
+        .and_then(|stride| stride.as_usize())
+        .unwrap_or(element_size);
+    if stride < element_size {
+        return Err(broken("a stride shorter than its elements"));
+    }
+    let data: &[u8] = buffer
+        .get(view_offset..view_offset.saturating_add(view_length))
+        .ok_or_else(|| broken("a buffer view past the end of its buffer"))?;
+
+    // The count and offsets come from the file and may be huge, so make sure
+    // the last element fits before reading any, without overflowing on the way
+    if let Some(last) = count.checked_sub(1) {
+        let end: Option<usize> = last
+            .checked_mul(stride)
+            .and_then(|start| start.checked_add(offset))
+            .and_then(|start| start.checked_add(element_size));
+        if end.is_none_or(|end| end > data.len()) {
+            return Err(broken("more elements than its buffer view holds"));
+        }
+    }
+
+    let mut elements: Vec<Vec<f64>> = Vec::with_capacity(count);
+    for element in 0..count {
+        let start: usize = offset + element * stride;
+        let mut values: Vec<f64> = Vec::with_capacity(width);
+        for i in 0..width {
+            values.push(
+                component(data, start + i * size, component_type, normalized)
+                    .ok_or_else(|| broken("data past the end of its buffer view"))?,
+            );
+        }
+        elements.push(values);
+    }
+    Ok(elements)
+}
+
+// Builds a mesh from a triangle, strip or fan primitive; points and lines are skipped
+fn read_primitive(
+    document: &Json,
+    buffers: &[Vec<u8>],
+    primitive: &Json,
+) -> Result<Option<Mesh>, LoadError> {
+    let mode: usize = primitive
+        .get("mode")
+        .and_then(|mode| mode.as_usize())
+        .unwrap_or(4);
+    if !(4..=6).contains(&mode) {
+        return Ok(None);
+    }
+
+    let attributes: Option<&Json> = primitive.get("attributes");
+    let attribute = |name: &str| -> Option<usize> { attributes?.get(name)?.as_usize() };
+    let to_vec3 = |values: Vec<Vec<f64>>| -> Vec<Vec3> {
+        values
+            .into_iter()
+            .map(|value| Vec3 {
+                x: value.first().copied().unwrap_or(0.0),
+                y: value.get(1).copied().unwrap_or(0.0),
+                z: value.get(2).copied().unwrap_or(0.0),
+            })
+            .collect()
+    };
+
+    let position: usize = attribute("POSITION")
+        .ok_or_else(|| invalid("primitive has no POSITION attribute".to_string()))?;
+    let vertices: Vec<Vec3> = to_vec3(read_accessor(document, buffers, position)?);
+    let normals: Option<Vec<Vec3>> = match attribute("NORMAL") {
+        Some(normal) => Some(to_vec3(read_accessor(document, buffers, normal)?)),
+        None => None,
+    };
+    let uvs: Option<Vec<(f64, f64)>> = match attribute("TEXCOORD_0") {
+        Some(uv) => Some(
+            read_accessor(document, buffers, uv)?
+                .into_iter()
+                .map(|uv| {
+                    (
+                        uv.first().copied().unwrap_or(0.0),
+                        uv.get(1).copied().unwrap_or(0.0),
+                    )
+                })
+                .collect(),
+        ),
+        None => None,
+    };
+
+    let order: Vec<usize> = match primitive
+        .get("indices")
+        .and_then(|indices| indices.as_usize())
+    {
+        Some(indices) => read_accessor(document, buffers, indices)?
+            .into_iter()
+            .map(|index| index.first().copied().unwrap_or(0.0) as usize)
+            .collect(),
+        None => (0..vertices.len()).collect(),
+    };
+    if let Some(index) = order.iter().find(|index| **index >= vertices.len()) {
+        return Err(invalid(format!(
+            "primitive index {} out of range (have {})",
+            index,
+            vertices.len()
+        )));
+    }
+
+    let indices: Vec<[usize; 3]> = match mode {
+        4 => order
+            .chunks_exact(3)
+            .map(|corners| [corners[0], corners[1], corners[2]])
+            .collect(),
+        // Every other strip triangle is wound the other way round
+        5 => (0..order.len().saturating_sub(2))
+            .map(|i| {
+                if i % 2 == 0 {
+                    [order[i], order[i + 1], order[i + 2]]
+                } else {
+                    [order[i + 1], order[i], order[i + 2]]
+                }
+            })
+            .collect(),
+        _ => (1..order.len().saturating_sub(1))
+            .map(|i| [order[0], order[i], order[i + 1]])
+            .collect(),
+    };
+
+    let mut mesh = Mesh::new(vertices, indices);
+    if let Some(normals) = normals.filter(|normals| normals.len() == mesh.vertices().len()) {
+        mesh.set_normals(normals);
+    }
+    if let Some(uvs) = uvs.filter(|uvs| uvs.len() == mesh.vertices().len()) {
+        mesh.set_uvs(uvs);
+    }
+    let color: Option<[f64; 4]> = primitive
+        .get("material")
+        .and_then(|material| material.as_usize())
+        .and_then(|material| list(document, "materials").get(material))
+        .and_then(|material| material.get("pbrMetallicRoughness"))
+        .map(|pbr| numbers(pbr.get("baseColorFactor"), [1.0; 4]));
+    if let Some([r, g, b, _]) = color {
+        mesh.set_color(Vec3 { x: r, y: g, z: b });
+    }
+    Ok(Some(mesh))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ray::Ray;
+
+    fn encode_base64(bytes: &[u8]) -> String {
+        const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+        let mut text: String = String::new();
+        for chunk in bytes.chunks(3) {
+            let word: u32 = chunk
+                .iter()
+                .enumerate()
+                .fold(0, |word, (i, byte)| word | (*byte as u32) << (16 - 8 * i));
+            for i in 0..4 {
+                if i <= chunk.len() {
+                    text.push(ALPHABET[(word >> (18 - 6 * i) & 63) as usize] as char);
+                } else {
+                    text.push('=');
+                }
+            }
+        }
+        text
+    }
+
+    // Three float positions followed by three u16 indices and two bytes of padding
+    fn triangle_buffer() -> Vec<u8> {
+        let mut bytes: Vec<u8> = Vec::new();
+        for value in [0.0f32, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0] {
+            bytes.extend(value.to_le_bytes());
+        }
+        for index in [0u16, 1, 2, 0] {
+            bytes.extend(index.to_le_bytes());
+        }
+        bytes
+    }
+
+    // A one triangle document. `buffer` is the buffer's JSON, and `position`
+    // the POSITION accessor's.
+    fn document(buffer: &str, position: &str) -> String {
+        format!(
+            r#"{{
+  "asset": {{"version": "2.0"}},
+  "buffers": [{}],
+  "bufferViews": [
+    {{"buffer": 0, "byteOffset": 0, "byteLength": 36}},
+    {{"buffer": 0, "byteOffset": 36, "byteLength": 6}}
+  ],
+  "accessors": [
+    {},
+    {{"bufferView": 1, "componentType": 5123, "count": 3, "type": "SCALAR"}}
+  ],
+  "meshes": [{{"primitives": [{{"attributes": {{"POSITION": 0}}, "indices": 1}}]}}],
+  "nodes": [{{"mesh": 0}}]
+}}"#,
+            buffer, position
+        )
+    }
+
+    const POSITION: &str =
+        r#"{"bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3"}"#;
+
+    fn embedded(position: &str) -> String {
+        let buffer: String = format!(
+            r#"{{"byteLength": 44, "uri": "data:application/octet-stream;base64,{}"}}"#,
+            encode_base64(&triangle_buffer())
+        );
+        document(&buffer, position)
+    }
+
+    fn no_files(_: &str) -> Option<Vec<u8>> {
+        None
+    }
+
+    fn assert_triangle(gltf: &Gltf) {
+        let mesh: &Mesh = &gltf.meshes[0][0];
+        assert_eq!(mesh.indices(), &[[0, 1, 2]]);
+        assert_eq!(
+            mesh.vertices()[2],
+            Vec3 {
+                x: 0.0,
+                y: 1.0,
+                z: 0.0
+            }
+        );
+        assert_eq!(gltf.roots, vec![0]);
+    }
+
+    fn is_invalid(result: Result<Gltf, LoadError>) -> bool {
+        matches!(result, Err(LoadError::Invalid(_)))
+    }
+
+    #[test]
+    fn embedded_base64_buffer() {
+        let gltf: Gltf = parse(&embedded(POSITION), None, no_files).unwrap();
+        assert_triangle(&gltf);
+    }
+
+    #[test]
+    fn nodes_keep_their_hierarchy() {
+        let nodes = r#""nodes": [
+    {"name": "parent", "translation": [0, 0, -5], "children": [1, 2]},
+    {"name": "big", "mesh": 0, "scale": [2, 2, 2]},
+    {"name": "aside", "mesh": 0, "translation": [10, 0, 0]}
+  ]"#;
+        let source: String = embedded(POSITION).replace(r#""nodes": [{"mesh": 0}]"#, nodes);
+        let mut scene: Scene = parse(&source, None, no_files).unwrap().to_scene();
+
+        let names: Vec<Option<&str>> = scene
+            .nodes
+            .iter()
+            .map(|node| node.name.as_deref())
+            .collect();
+        assert_eq!(names, vec![Some("parent"), Some("big"), Some("aside")]);
+        assert!(scene.nodes[0].geometry.is_none());
+        assert!(Rc::ptr_eq(
+            scene.nodes[1].geometry.as_ref().unwrap(),
+            scene.nodes[2].geometry.as_ref().unwrap()
+        ));
+        assert!(scene.meshes.is_empty());
+        assert_eq!(scene.mesh_bounds().unwrap().max.x, 11.0);
+
+        // Inside the scaled triangle only, once the parent has put it 5 away
+        let r = Ray::new(
+            Vec3 {
+                x: 0.0,
+                y: 0.0,
+                z: 0.0,
+            },
+            Vec3 {
+                x: 0.7,
+                y: 0.7,
+                z: -5.0,
+            },
+        );
+        assert!((scene.intersect(&r).unwrap().t - 1.0).abs() < 1e-9);
+
+        // Moving the parent carries its children along
+        scene.nodes[0].transform = scene.nodes[0].transform.translate(Vec3 {
+            x: 0.0,
+            y: 0.0,
+            z: -5.0,
+        });
+        scene.update_instances();
+        assert!(scene.intersect(&r).is_none());
+        let r = Ray::new(
+            r.origin(),
+            Vec3 {
+                x: 0.7,
+                y: 0.7,
+                z: -10.0,
+            },
+        );
+        assert!((scene.intersect(&r).unwrap().t - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn primitives_hang_under_their_node() {
+        let primitive = r#"{"attributes": {"POSITION": 0}, "indices": 1}"#;
+        let source: String = embedded(POSITION).replace(
+            &format!(r#""primitives": [{}]"#, primitive),
+            &format!(r#""primitives": [{}, {}]"#, primitive, primitive),
+        );
+        let scene: Scene = parse(&source, None, no_files).unwrap().to_scene();
+        assert_eq!(scene.nodes.len(), 3);
+        assert!(scene.nodes[0].geometry.is_none());
+        assert!(scene.nodes[1..].iter().all(|node| node.geometry.is_some()));
+    }
+
+    #[test]
+    fn glb_round_trip() {
+        let mut json: Vec<u8> = document(r#"{"byteLength": 44}"#, POSITION).into_bytes();
+        while !json.len().is_multiple_of(4) {
+            json.push(b' ');
+        }
+        let binary: Vec<u8> = triangle_buffer();
+
+        let mut bytes: Vec<u8> = GLB_MAGIC.to_vec();
+        bytes.extend(2u32.to_le_bytes());
+        bytes.extend(((12 + 8 + json.len() + 8 + binary.len()) as u32).to_le_bytes());
+        bytes.extend((json.len() as u32).to_le_bytes());
+        bytes.extend(CHUNK_JSON.to_le_bytes());
+        bytes.extend(&json);
+        bytes.extend((binary.len() as u32).to_le_bytes());
+        bytes.extend(CHUNK_BIN.to_le_bytes());
+        bytes.extend(&binary);
+        assert_triangle(&parse_glb(&bytes, no_files).unwrap());
+
+        bytes.truncate(bytes.len() - 1);
+        assert!(is_invalid(parse_glb(&bytes, no_files)));
+    }
+
+    #[test]
+    fn huge_counts_are_an_error() {
+        // With no buffer view the accessor would be a quadrillion zeros
+        let zeros = r#"{"componentType": 5126, "count": 1e15, "type": "VEC3"}"#;
+        assert!(is_invalid(parse(&embedded(zeros), None, no_files)));
+
+        let past_view = r#"{"bufferView": 0, "componentType": 5126, "count": 4, "type": "VEC3"}"#;
+        assert!(is_invalid(parse(&embedded(past_view), None, no_files)));
+
+        let enormous = r#"{"bufferView": 0, "componentType": 5126, "count": 1e30, "type": "VEC3"}"#;
+        assert!(is_invalid(parse(&embedded(enormous), None, no_files)));
+    }
+
+    #[test]
+    fn huge_offsets_are_an_error() {
+        for offset in ["40", "1e30", "18446744073709551615"] {
+            let position: String = format!(
+                r#"{{"bufferView": 0, "byteOffset": {}, "componentType": 5126, "count": 3, "type": "VEC3"}}"#,
+                offset
+            );
+            assert!(
+                is_invalid(parse(&embedded(&position), None, no_files)),
+                "offset {}",
+                offset
+            );
+        }
+    }
+
+    #[test]
+    fn short_strides_are_an_error() {
+        let mut source: String = embedded(POSITION);
+        source = source.replacen(
+            r#""byteLength": 36}"#,
+            r#""byteLength": 36, "byteStride": 4}"#,
+            1,
+        );
+        assert!(is_invalid(parse(&source, None, no_files)));
+    }
+}
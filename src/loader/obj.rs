@@ -0,0 +1,365 @@
+use super::{parse_error, parse_number, parse_vec3, LoadError};
+use crate::mesh::Mesh;
+use crate::vector::Vec3;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+// One vertex of a face: indices into the position, uv and normal lists
+type Corner = (usize, Option<usize>, Option<usize>);
+
+// Faces collected for one `o`/`g` group and material
+struct Group {
+    name: Option<String>,
+    material: Option<String>,
+    vertices: Vec<Vec3>,
+    uvs: Vec<Option<(f64, f64)>>,
+    normals: Vec<Option<Vec3>>,
+    indices: Vec<[usize; 3]>,
+    // OBJ indexes positions, uvs and normals separately, meshes share one index
+    lookup: HashMap<Corner, usize>,
+}
+
+impl Group {
+    fn new(name: Option<String>, material: Option<String>) -> Group {
+        Group {
+            name,
+            material,
+            vertices: Vec::new(),
+            uvs: Vec::new(),
+            normals: Vec::new(),
+            indices: Vec::new(),
+            lookup: HashMap::new(),
+        }
+    }
+
+    fn vertex(
+        &mut self,
+        corner: Corner,
+        positions: &[Vec3],
+        uvs: &[(f64, f64)],
+        normals: &[Vec3],
+    ) -> usize {
+        if let Some(index) = self.lookup.get(&corner) {
+            return *index;
+        }
+        let (position, uv, normal) = corner;
+        self.vertices.push(positions[position]);
+        self.uvs.push(uv.map(|uv| uvs[uv]));
+        self.normals.push(normal.map(|normal| normals[normal]));
+        self.lookup.insert(corner, self.vertices.len() - 1);
+        self.vertices.len() - 1
+    }
+
+    fn into_mesh(self, materials: &HashMap<String, Vec3>) -> Mesh {
+        let uvs: Option<Vec<(f64, f64)>> = self.uvs.into_iter().collect();
+        let normals: Option<Vec<Vec3>> = self.normals.into_iter().collect();
+
+        let mut mesh = Mesh::new(self.vertices, self.indices);
+        if let Some(uvs) = uvs {
+            mesh.set_uvs(uvs);
+        }
+        if let Some(normals) = normals {
+            mesh.set_normals(normals);
+        }
+        if let Some(name) = &self.name {
+            mesh.set_name(name);
+        }
+        if let Some(color) = self.material.and_then(|material| materials.get(&material)) {
+            mesh.set_color(*color);
+        }
+        mesh
+    }
+}
+
+// Reads an .obj file, along with any .mtl libraries next to it
+pub fn load(path: &Path) -> Result<Vec<Mesh>, LoadError> {
+    let source: String = fs::read_to_string(path)?;
+    let directory: &Path = path.parent().unwrap_or(Path::new(""));
+    parse(&source, |name| {
+        fs::read_to_string(directory.join(name)).ok()
+    })
+}
+
+// Parses OBJ source into one mesh per group. `read_mtl` returns the contents of a
+// material library named by `mtllib`; libraries it can't find are skipped.
+pub fn parse(
+    source: &str,
+    read_mtl: impl Fn(&str) -> Option<String>,
+) -> Result<Vec<Mesh>, LoadError> {
+    let mut positions: Vec<Vec3> = Vec::new();
+    let mut uvs: Vec<(f64, f64)> = Vec::new();
+    let mut normals: Vec<Vec3> = Vec::new();
+    let mut materials: HashMap<String, Vec3> = HashMap::new();
+
+    let mut groups: Vec<Group> = Vec::new();
+    let mut current = Group::new(None, None);
+
+    for (number, line) in source.lines().enumerate() {
+        let line_number = number + 1;
+        let line: &str = line.split('#').next().unwrap_or("");
+        let mut tokens = line.split_whitespace();
+        let keyword: &str = match tokens.next() {
+            Some(keyword) => keyword,
+            None => continue,
+        };
+        let rest: Vec<&str> = tokens.collect();
+
+        match keyword {
+            "v" => positions.push(parse_vec3(&rest, line_number)?),
+            "vn" => normals.push(parse_vec3(&rest, line_number)?),
+            "vt" => {
+                let u: f64 = parse_number(rest.first(), line_number)?;
+                let v: f64 = match rest.get(1) {
+                    Some(_) => parse_number(rest.get(1), line_number)?,
+                    None => 0.0,
+                };
+                uvs.push((u, v));
+            }
+            "f" => {
+                if rest.len() < 3 {
+                    return Err(parse_error(line_number, "face needs at least 3 vertices"));
+                }
+                let counts = (positions.len(), uvs.len(), normals.len());
+                let mut corners: Vec<usize> = Vec::new();
+                for token in &rest {
+                    let corner: Corner = parse_corner(token, counts, line_number)?;
+                    corners.push(current.vertex(corner, &positions, &uvs, &normals));
+                }
+                // Fan triangulation, fine for the convex polygons OBJ exporters write
+                for i in 1..corners.len() - 1 {
+                    current
+                        .indices
+                        .push([corners[0], corners[i], corners[i + 1]]);
+                }
+            }
+            "o" | "g" => {
+                let material: Option<String> = current.material.clone();
+                groups.push(current);
+                current = Group::new(Some(rest.join(" ")), material);
+            }
+            "usemtl" => {
+                let material: String = rest.join(" ");
+                if current.material.as_deref() != Some(material.as_str()) {
+                    let name: Option<String> = current.name.clone();
+                    groups.push(current);
+                    current = Group::new(name, Some(material));
+                }
+            }
+            "mtllib" => {
+                for library in &rest {
+                    if let Some(source) = read_mtl(library) {
+                        materials.extend(parse_mtl(&source).map_err(|err| match err {
+                            LoadError::Parse { line, message } => LoadError::Parse {
+                                line,
+                                message: format!("{}: {}", library, message),
+                            },
+                            err => err,
+                        })?);
+                    }
+                }
+            }
+            // Smoothing groups, lines, points and the rest don't affect rendering
+            _ => {}
+        }
+    }
+    groups.push(current);
+
+    Ok(groups
+        .into_iter()
+        .filter(|group| !group.indices.is_empty())
+        .map(|group| group.into_mesh(&materials))
+        .collect())
+}
+
+// Diffuse (Kd) color of every material in an .mtl library
+pub fn parse_mtl(source: &str) -> Result<HashMap<String, Vec3>, LoadError> {
+    let mut materials: HashMap<String, Vec3> = HashMap::new();
+    let mut current: Option<String> = None;
+
+    for (number, line) in source.lines().enumerate() {
+        let line_number = number + 1;
+        let line: &str = line.split('#').next().unwrap_or("");
+        let mut tokens = line.split_whitespace();
+        let keyword: &str = match tokens.next() {
+            Some(keyword) => keyword,
+            None => continue,
+        };
+        let rest: Vec<&str> = tokens.collect();
+
+        match keyword {
+            "newmtl" => current = Some(rest.join(" ")),
+            "Kd" => {
+                let name: &String = current
+                    .as_ref()
+                    .ok_or_else(|| parse_error(line_number, "Kd before any newmtl"))?;
+                materials.insert(name.clone(), parse_vec3(&rest, line_number)?);
+            }
+            _ => {}
+        }
+    }
+    Ok(materials)
+}
+
+// Turns a 1-based (or negative, counting back from the end) index into a 0-based one
+fn resolve_index(token: &str, count: usize, line: usize) -> Result<usize, LoadError> {
+    let index: i64 = token
+        .parse::<i64>()
+        .map_err(|_| parse_error(line, &format!("invalid index '{}'", token)))?;
+    let resolved: i64 = if index < 0 {
+        count as i64 + index
+    } else {
+        index - 1
+    };
+    if index == 0 || resolved < 0 || resolved >= count as i64 {
+        return Err(parse_error(
+            line,
+            &format!("index {} out of range (have {})", index, count),
+        ));
+    }
+    Ok(resolved as usize)
+}
+
+// Face vertex in any of the forms v, v/vt, v//vn or v/vt/vn
+fn parse_corner(
+    token: &str,
+    counts: (usize, usize, usize),
+    line: usize,
+) -> Result<Corner, LoadError> {
+    let mut parts = token.split('/');
+    let position: usize = resolve_index(parts.next().unwrap_or(""), counts.0, line)?;
+    let uv: Option<usize> = match parts.next() {
+        Some(part) if !part.is_empty() => Some(resolve_index(part, counts.1, line)?),
+        _ => None,
+    };
+    let normal: Option<usize> = match parts.next() {
+        Some(part) if !part.is_empty() => Some(resolve_index(part, counts.2, line)?),
+        _ => None,
+    };
+    Ok((position, uv, normal))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn no_mtl(_: &str) -> Option<String> {
+        None
+    }
+
+    fn line_of(err: LoadError) -> usize {
+        match err {
+            LoadError::Parse { line, .. } => line,
+            err => panic!("expected a parse error, got {:?}", err),
+        }
+    }
+
+    const SQUARE: &str = "\
+v 0 0 0
+v 1 0 0
+v 1 1 0
+v 0 1 0
+";
+
+    #[test]
+    fn fans_polygons_into_triangles() {
+        let source = format!("{}v 0.5 1.5 0\nf 1 2 3 5 4\n", SQUARE);
+        let meshes: Vec<Mesh> = parse(&source, no_mtl).unwrap();
+        assert_eq!(meshes.len(), 1);
+        assert_eq!(meshes[0].indices(), &[[0, 1, 2], [0, 2, 3], [0, 3, 4]]);
+    }
+
+    #[test]
+    fn negative_indices_count_back_from_the_latest() {
+        let source = format!("{}f -4 -3 -2 -1\n", SQUARE);
+        let meshes: Vec<Mesh> = parse(&source, no_mtl).unwrap();
+        assert_eq!(meshes[0].vertices()[0].x, 0.0);
+        assert_eq!(meshes[0].vertices()[3].y, 1.0);
+        assert_eq!(meshes[0].indices(), &[[0, 1, 2], [0, 2, 3]]);
+    }
+
+    #[test]
+    fn corners_with_uvs_and_normals() {
+        let source = format!(
+            "{}vt 0 0\nvt 1 0\nvt 1 1\nvn 0 0 1\nf 1/1/1 2/2/1 3/3/1\nf 1//1 3//1 4//1\n",
+            SQUARE
+        );
+        let meshes: Vec<Mesh> = parse(&source, no_mtl).unwrap();
+        // Corners repeat only when every index matches, so 1/1/1 and 1//1 differ
+        assert_eq!(meshes[0].vertices().len(), 6);
+        assert_eq!(meshes[0].normals().unwrap().len(), 6);
+        // Not every corner has a uv, so the mesh has none
+        assert!(meshes[0].uvs().is_none());
+    }
+
+    #[test]
+    fn groups_and_objects_split_meshes() {
+        let source = format!(
+            "{}o first\nf 1 2 3\ng second part\nf 1 3 4\ng empty\n",
+            SQUARE
+        );
+        let meshes: Vec<Mesh> = parse(&source, no_mtl).unwrap();
+        let names: Vec<Option<&str>> = meshes.iter().map(|mesh| mesh.name()).collect();
+        assert_eq!(names, [Some("first"), Some("second part")]);
+        // Every group indexes its own vertices
+        assert_eq!(meshes[1].indices(), &[[0, 1, 2]]);
+    }
+
+    #[test]
+    fn materials_color_their_faces() {
+        let mtl = "newmtl red\nKd 1 0 0\n\nnewmtl blue  # the other one\nKd 0 0 1\n";
+        let source = format!(
+            "mtllib colors.mtl\n{}usemtl red\nf 1 2 3\nusemtl blue\nf 1 3 4\nusemtl missing\nf 2 3 4\n",
+            SQUARE
+        );
+        let meshes: Vec<Mesh> = parse(&source, |name| {
+            assert_eq!(name, "colors.mtl");
+            Some(mtl.to_string())
+        })
+        .unwrap();
+        let colors: Vec<Option<Vec3>> = meshes.iter().map(|mesh| mesh.color()).collect();
+        let red = Vec3 {
+            x: 1.0,
+            y: 0.0,
+            z: 0.0,
+        };
+        let blue = Vec3 {
+            x: 0.0,
+            y: 0.0,
+            z: 1.0,
+        };
+        assert_eq!(colors, [Some(red), Some(blue), None]);
+    }
+
+    #[test]
+    fn missing_libraries_are_skipped() {
+        let source = format!("mtllib nowhere.mtl\n{}usemtl red\nf 1 2 3\n", SQUARE);
+        let meshes: Vec<Mesh> = parse(&source, no_mtl).unwrap();
+        assert_eq!(meshes[0].color(), None);
+    }
+
+    #[test]
+    fn errors_point_at_their_line() {
+        let bad = |source: &str| line_of(parse(source, no_mtl).unwrap_err());
+        assert_eq!(bad("v 0 0 0\nv 1 x 0\n"), 2);
+        assert_eq!(bad("v 0 0\n"), 1);
+        assert_eq!(bad(&format!("{}\nf 1 2\n", SQUARE)), 6);
+        assert_eq!(bad(&format!("{}f 1 2 5\n", SQUARE)), 5);
+        assert_eq!(bad(&format!("{}f 0 1 2\n", SQUARE)), 5);
+        assert_eq!(bad(&format!("{}f -5 1 2\n", SQUARE)), 5);
+        assert_eq!(bad(&format!("{}f 1/1 2/1 3/1\n", SQUARE)), 5);
+        assert_eq!(bad(&format!("{}f 1 2 three\n", SQUARE)), 5);
+    }
+
+    #[test]
+    fn mtl_errors_name_the_library() {
+        let source = "mtllib bad.mtl\n";
+        let err: LoadError = parse(source, |_| Some("Kd 1 0 0\n".to_string())).unwrap_err();
+        assert!(err.to_string().contains("bad.mtl"));
+        assert_eq!(line_of(err), 1);
+        assert_eq!(
+            line_of(parse_mtl("newmtl a\nKd 1 0 0\nnewmtl b\nKd 1 nope 0\n").unwrap_err()),
+            4
+        );
+    }
+}
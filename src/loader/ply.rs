@@ -0,0 +1,593 @@
+use super::{parse_error, LoadError};
+use crate::mesh::Mesh;
+use crate::vector::Vec3;
+use libm::sqrt;
+use std::fs;
+use std::path::Path;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Format {
+    Ascii,
+    BinaryLittleEndian,
+    BinaryBigEndian,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Scalar {
+    Int8,
+    UInt8,
+    Int16,
+    UInt16,
+    Int32,
+    UInt32,
+    Float32,
+    Float64,
+}
+
+impl Scalar {
+    fn from_name(name: &str) -> Option<Scalar> {
+        match name {
+            "char" | "int8" => Some(Scalar::Int8),
+            "uchar" | "uint8" => Some(Scalar::UInt8),
+            "short" | "int16" => Some(Scalar::Int16),
+            "ushort" | "uint16" => Some(Scalar::UInt16),
+            "int" | "int32" => Some(Scalar::Int32),
+            "uint" | "uint32" => Some(Scalar::UInt32),
+            "float" | "float32" => Some(Scalar::Float32),
+            "double" | "float64" => Some(Scalar::Float64),
+            _ => None,
+        }
+    }
+
+    fn size(&self) -> usize {
+        match self {
+            Scalar::Int8 | Scalar::UInt8 => 1,
+            Scalar::Int16 | Scalar::UInt16 => 2,
+            Scalar::Int32 | Scalar::UInt32 | Scalar::Float32 => 4,
+            Scalar::Float64 => 8,
+        }
+    }
+
+    // Largest value of an integer color channel, used to bring colors into 0 to 1
+    fn color_range(&self) -> f64 {
+        match self {
+            Scalar::UInt8 => 255.0,
+            Scalar::UInt16 => 65535.0,
+            _ => 1.0,
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Property {
+    Scalar(String, Scalar),
+    List {
+        name: String,
+        count: Scalar,
+        item: Scalar,
+    },
+}
+
+impl Property {
+    fn name(&self) -> &str {
+        match self {
+            Property::Scalar(name, _) => name,
+            Property::List { name, .. } => name,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+impl Element {
+    // Fewest bytes one instance takes in a binary body, with every list empty
+    fn minimum_size(&self) -> usize {
+        self.properties
+            .iter()
+            .map(|property| match property {
+                Property::Scalar(_, scalar) => scalar.size(),
+                Property::List { count, .. } => count.size(),
+            })
+            .sum()
+    }
+    fn property(&self, name: &str) -> Option<usize> {
+        self.properties
+            .iter()
+            .position(|property| property.name() == name)
+    }
+}
+
+// Everything up to end_header, plus the body that follows it
+struct Header<'a> {
+    format: Format,
+    elements: Vec<Element>,
+    body: &'a [u8],
+    lines: usize, // so ASCII body errors can give file line numbers
+}
+
+// Walks the binary body of a file, one value at a time
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+    big_endian: bool,
+}
+
+impl Reader<'_> {
+    fn read(&mut self, scalar: Scalar) -> Option<f64> {
+        let size: usize = scalar.size();
+        let mut word = [0u8; 8];
+        word[..size].copy_from_slice(self.bytes.get(self.position..self.position + size)?);
+        if self.big_endian {
+            word[..size].reverse();
+        }
+        self.position += size;
+
+        let value: f64 = match scalar {
+            Scalar::Int8 => word[0] as i8 as f64,
+            Scalar::UInt8 => word[0] as f64,
+            Scalar::Int16 => i16::from_le_bytes([word[0], word[1]]) as f64,
+            Scalar::UInt16 => u16::from_le_bytes([word[0], word[1]]) as f64,
+            Scalar::Int32 => i32::from_le_bytes([word[0], word[1], word[2], word[3]]) as f64,
+            Scalar::UInt32 => u32::from_le_bytes([word[0], word[1], word[2], word[3]]) as f64,
+            Scalar::Float32 => f32::from_le_bytes([word[0], word[1], word[2], word[3]]) as f64,
+            Scalar::Float64 => f64::from_le_bytes(word),
+        };
+        Some(value)
+    }
+}
+
+// Reads a .ply file, centered on the origin and scaled to fit a 2 unit cube.
+// Files without faces come back as a point cloud.
+pub fn load(path: &Path) -> Result<Mesh, LoadError> {
+    let bytes: Vec<u8> = fs::read(path)?;
+    let mut mesh: Mesh = parse(&bytes)?.fitted(2.0);
+    if mesh.triangle_count() == 0 {
+        // Roughly half the spacing of points spread over the surface of the model
+        let count: f64 = mesh.vertices().len() as f64;
+        mesh.set_point_radius((1.0 / sqrt(count)).min(0.05));
+    }
+    Ok(mesh)
+}
+
+pub fn parse(bytes: &[u8]) -> Result<Mesh, LoadError> {
+    let Header {
+        format,
+        elements,
+        body,
+        lines: header_lines,
+    } = parse_header(bytes)?;
+
+    // Every element is read, even ones we ignore, to find where the next one starts
+    let mut instances: Vec<Vec<Vec<Vec<f64>>>> = Vec::new();
+    match format {
+        Format::Ascii => {
+            let source: &str = std::str::from_utf8(body)
+                .map_err(|_| LoadError::Invalid("ASCII PLY body is not text".to_string()))?;
+            let mut lines = source
+                .lines()
+                .enumerate()
+                .map(|(number, line)| (header_lines + number + 1, line))
+                .filter(|(_, line)| !line.trim().is_empty());
+            for element in &elements {
+                let mut values: Vec<Vec<Vec<f64>>> =
+                    Vec::with_capacity(element.count.min(body.len()));
+                for _ in 0..element.count {
+                    let (line_number, line) = lines.next().ok_or_else(|| {
+                        LoadError::Invalid(format!(
+                            "file ended before all {} {} elements",
+                            element.count, element.name
+                        ))
+                    })?;
+                    values.push(parse_ascii_instance(element, line, line_number)?);
+                }
+                instances.push(values);
+            }
+        }
+        Format::BinaryLittleEndian | Format::BinaryBigEndian => {
+            let mut reader = Reader {
+                bytes: body,
+                position: 0,
+                big_endian: format == Format::BinaryBigEndian,
+            };
+            let truncated = || LoadError::Invalid("truncated binary PLY".to_string());
+            for element in &elements {
+                // The count comes from the header, so check the body could hold
+                // that many before looping over them
+                if element.count > 0 && element.properties.is_empty() {
+                    return Err(LoadError::Invalid(format!(
+                        "PLY element {} has no properties",
+                        element.name
+                    )));
+                }
+                let needed: Option<usize> = element.count.checked_mul(element.minimum_size());
+                if needed.is_none_or(|needed| needed > body.len() - reader.position) {
+                    return Err(truncated());
+                }
+                let mut values: Vec<Vec<Vec<f64>>> =
+                    Vec::with_capacity(element.count.min(body.len()));
+                for _ in 0..element.count {
+                    let mut instance: Vec<Vec<f64>> = Vec::with_capacity(element.properties.len());
+                    for property in &element.properties {
+                        match property {
+                            Property::Scalar(_, scalar) => {
+                                instance.push(vec![reader.read(*scalar).ok_or_else(truncated)?])
+                            }
+                            Property::List { count, item, .. } => {
+                                let length: f64 = reader.read(*count).ok_or_else(truncated)?;
+                                let mut list: Vec<f64> = Vec::new();
+                                for _ in 0..length.max(0.0) as usize {
+                                    list.push(reader.read(*item).ok_or_else(truncated)?);
+                                }
+                                instance.push(list);
+                            }
+                        }
+                    }
+                    values.push(instance);
+                }
+                instances.push(values);
+            }
+        }
+    }
+
+    build_mesh(&elements, &instances)
+}
+
+fn parse_header(bytes: &[u8]) -> Result<Header<'_>, LoadError> {
+    let end: usize = bytes
+        .windows(b"end_header".len())
+        .position(|window| window == b"end_header")
+        .ok_or_else(|| LoadError::Invalid("PLY header has no end_header".to_string()))?;
+    let body_start: usize = match bytes[end..].iter().position(|byte| *byte == b'\n') {
+        Some(newline) => end + newline + 1,
+        None => bytes.len(),
+    };
+    let header: &str = std::str::from_utf8(&bytes[..end])
+        .map_err(|_| LoadError::Invalid("PLY header is not text".to_string()))?;
+
+    let mut format: Option<Format> = None;
+    let mut elements: Vec<Element> = Vec::new();
+    let mut header_lines: usize = 1; // end_header itself
+    for (number, line) in header.lines().enumerate() {
+        let line_number = number + 1;
+        header_lines += 1;
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+
+        match tokens.first() {
+            Some(&"ply") if line_number == 1 => {}
+            _ if line_number == 1 => return Err(parse_error(line_number, "not a PLY file")),
+            Some(&"format") => {
+                format = Some(match tokens.get(1) {
+                    Some(&"ascii") => Format::Ascii,
+                    Some(&"binary_little_endian") => Format::BinaryLittleEndian,
+                    Some(&"binary_big_endian") => Format::BinaryBigEndian,
+                    _ => return Err(parse_error(line_number, "unknown PLY format")),
+                });
+            }
+            Some(&"element") => {
+                let (name, count) = match (tokens.get(1), tokens.get(2)) {
+                    (Some(name), Some(count)) => (name, count),
+                    _ => return Err(parse_error(line_number, "expected element name and count")),
+                };
+                let count: usize = count.parse::<usize>().map_err(|_| {
+                    parse_error(line_number, &format!("invalid element count '{}'", count))
+                })?;
+                elements.push(Element {
+                    name: name.to_string(),
+                    count,
+                    properties: Vec::new(),
+                });
+            }
+            Some(&"property") => {
+                let element: &mut Element = elements
+                    .last_mut()
+                    .ok_or_else(|| parse_error(line_number, "property before any element"))?;
+                let scalar = |token: Option<&&str>| -> Result<Scalar, LoadError> {
+                    let name: &str = token.unwrap_or(&"");
+                    Scalar::from_name(name).ok_or_else(|| {
+                        parse_error(line_number, &format!("unknown property type '{}'", name))
+                    })
+                };
+                let property: Property = if tokens.get(1) == Some(&"list") {
+                    Property::List {
+                        count: scalar(tokens.get(2))?,
+                        item: scalar(tokens.get(3))?,
+                        name: tokens.get(4).unwrap_or(&"").to_string(),
+                    }
+                } else {
+                    Property::Scalar(
+                        tokens.get(2).unwrap_or(&"").to_string(),
+                        scalar(tokens.get(1))?,
+                    )
+                };
+                element.properties.push(property);
+            }
+            // comment and obj_info lines
+            _ => {}
+        }
+    }
+
+    let format: Format =
+        format.ok_or_else(|| LoadError::Invalid("PLY header has no format".to_string()))?;
+    Ok(Header {
+        format,
+        elements,
+        body: &bytes[body_start..],
+        lines: header_lines,
+    })
+}
+
+fn parse_ascii_instance(
+    element: &Element,
+    line: &str,
+    line_number: usize,
+) -> Result<Vec<Vec<f64>>, LoadError> {
+    let mut tokens = line.split_whitespace();
+    let mut next = || -> Result<f64, LoadError> {
+        let token: &str = tokens
+            .next()
+            .ok_or_else(|| parse_error(line_number, "missing value"))?;
+        token
+            .parse::<f64>()
+            .map_err(|_| parse_error(line_number, &format!("invalid number '{}'", token)))
+    };
+
+    let mut instance: Vec<Vec<f64>> = Vec::with_capacity(element.properties.len());
+    for property in &element.properties {
+        match property {
+            Property::Scalar(..) => instance.push(vec![next()?]),
+            Property::List { .. } => {
+                let length: f64 = next()?;
+                let mut list: Vec<f64> = Vec::new();
+                for _ in 0..length.max(0.0) as usize {
+                    list.push(next()?);
+                }
+                instance.push(list);
+            }
+        }
+    }
+    Ok(instance)
+}
+
+fn build_mesh(elements: &[Element], instances: &[Vec<Vec<Vec<f64>>>]) -> Result<Mesh, LoadError> {
+    let vertex: usize = elements
+        .iter()
+        .position(|element| element.name == "vertex")
+        .ok_or_else(|| LoadError::Invalid("PLY file has no vertex element".to_string()))?;
+    let layout: &Element = &elements[vertex];
+    let find = |names: [&str; 3]| -> Option<[usize; 3]> {
+        Some([
+            layout.property(names[0])?,
+            layout.property(names[1])?,
+            layout.property(names[2])?,
+        ])
+    };
+    let position: [usize; 3] = find(["x", "y", "z"])
+        .ok_or_else(|| LoadError::Invalid("PLY vertices have no x, y and z".to_string()))?;
+    let normal: Option<[usize; 3]> = find(["nx", "ny", "nz"]);
+    let color: Option<[usize; 3]> = find(["red", "green", "blue"]);
+
+    let read = |instance: &Vec<Vec<f64>>, [x, y, z]: [usize; 3], scale: f64| -> Vec3 {
+        let value = |index: usize| instance[index].first().copied().unwrap_or(0.0) / scale;
+        Vec3 {
+            x: value(x),
+            y: value(y),
+            z: value(z),
+        }
+    };
+    let vertices: Vec<Vec3> = instances[vertex]
+        .iter()
+        .map(|instance| read(instance, position, 1.0))
+        .collect();
+    let normals: Option<Vec<Vec3>> = normal.map(|normal| {
+        instances[vertex]
+            .iter()
+            .map(|instance| read(instance, normal, 1.0))
+            .collect()
+    });
+    let colors: Option<Vec<Vec3>> = color.map(|color| {
+        let range: f64 = match &layout.properties[color[0]] {
+            Property::Scalar(_, scalar) => scalar.color_range(),
+            Property::List { .. } => 1.0,
+        };
+        instances[vertex]
+            .iter()
+            .map(|instance| read(instance, color, range))
+            .collect()
+    });
+
+    let mut indices: Vec<[usize; 3]> = Vec::new();
+    if let Some(face) = elements.iter().position(|element| element.name == "face") {
+        let list: usize = elements[face]
+            .property("vertex_indices")
+            .or_else(|| elements[face].property("vertex_index"))
+            .ok_or_else(|| LoadError::Invalid("PLY faces have no vertex_indices".to_string()))?;
+        for instance in &instances[face] {
+            let corners: &Vec<f64> = &instance[list];
+            for &corner in corners {
+                if corner < 0.0 || corner as usize >= vertices.len() {
+                    return Err(LoadError::Invalid(format!(
+                        "face index {} out of range (have {})",
+                        corner,
+                        vertices.len()
+                    )));
+                }
+            }
+            // Fan triangulation, as for OBJ faces
+            for i in 1..corners.len().saturating_sub(1) {
+                indices.push([
+                    corners[0] as usize,
+                    corners[i] as usize,
+                    corners[i + 1] as usize,
+                ]);
+            }
+        }
+    }
+
+    if vertices.is_empty() {
+        return Err(LoadError::Invalid("PLY file has no vertices".to_string()));
+    }
+    let mut mesh = Mesh::new(vertices, indices);
+    if let Some(normals) = normals {
+        mesh.set_normals(normals);
+    }
+    if let Some(colors) = colors {
+        mesh.set_colors(colors);
+    }
+    Ok(mesh)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TRIANGLE: [[f32; 3]; 3] = [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]];
+
+    // A triangle mesh with uchar colors, in the given binary format
+    fn binary(format: &str, big_endian: bool) -> Vec<u8> {
+        let mut bytes: Vec<u8> = format!(
+            "ply\nformat {} 1.0\ncomment made for a test\nelement vertex 3\n\
+             property float x\nproperty float y\nproperty float z\n\
+             property uchar red\nproperty uchar green\nproperty uchar blue\n\
+             element face 1\nproperty list uchar int vertex_indices\nend_header\n",
+            format
+        )
+        .into_bytes();
+        let order = |mut word: Vec<u8>| {
+            if big_endian {
+                word.reverse();
+            }
+            word
+        };
+        for corner in TRIANGLE {
+            for component in corner {
+                bytes.extend(order(component.to_le_bytes().to_vec()));
+            }
+            bytes.extend([255, 0, 51]);
+        }
+        bytes.push(3);
+        for index in [0i32, 1, 2] {
+            bytes.extend(order(index.to_le_bytes().to_vec()));
+        }
+        bytes
+    }
+
+    fn assert_triangle(mesh: &Mesh) {
+        assert_eq!(mesh.indices(), &[[0, 1, 2]]);
+        assert_eq!(mesh.vertices()[1].x, 1.0);
+        assert_eq!(mesh.vertices()[2].y, 1.0);
+    }
+
+    fn invalid(bytes: &[u8]) -> String {
+        match parse(bytes) {
+            Err(LoadError::Invalid(message)) => message,
+            result => panic!("expected invalid data, got {:?}", result.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn ascii_mesh_with_a_quad() {
+        let source = "ply
+format ascii 1.0
+element vertex 4
+property double x
+property double y
+property double z
+property float nx
+property float ny
+property float nz
+element face 1
+property list uchar int vertex_indices
+end_header
+0 0 0 0 0 1
+1 0 0 0 0 1
+
+1 1 0 0 0 1
+0 1 0 0 0 1
+4 0 1 2 3
+";
+        let mesh: Mesh = parse(source.as_bytes()).unwrap();
+        assert_eq!(mesh.indices(), &[[0, 1, 2], [0, 2, 3]]);
+        assert_eq!(mesh.normals().unwrap()[2].z, 1.0);
+    }
+
+    #[test]
+    fn binary_little_and_big_endian() {
+        for (format, big_endian) in [("binary_little_endian", false), ("binary_big_endian", true)] {
+            let mesh: Mesh = parse(&binary(format, big_endian)).unwrap();
+            assert_triangle(&mesh);
+            assert_eq!(
+                mesh.colors().unwrap()[0],
+                Vec3 {
+                    x: 1.0,
+                    y: 0.0,
+                    z: 0.2
+                }
+            );
+        }
+    }
+
+    #[test]
+    fn vertices_alone_are_a_point_cloud() {
+        let source = "ply\nformat ascii 1.0\nelement vertex 2\nproperty float x\n\
+                      property float y\nproperty float z\nend_header\n0 0 0\n2 4 6\n";
+        let path = std::env::temp_dir().join(format!("points-{}.ply", std::process::id()));
+        fs::write(&path, source).unwrap();
+        let mesh: Result<Mesh, LoadError> = load(&path);
+        fs::remove_file(&path).unwrap();
+
+        let mesh: Mesh = mesh.unwrap();
+        assert_eq!(mesh.triangle_count(), 0);
+        assert_eq!(mesh.vertices().len(), 2);
+        assert!(mesh.point_radius().is_some());
+    }
+
+    #[test]
+    fn truncated_binary_is_an_error() {
+        let bytes: Vec<u8> = binary("binary_little_endian", false);
+        for cut in [1, 5, 20] {
+            invalid(&bytes[..bytes.len() - cut]);
+        }
+    }
+
+    #[test]
+    fn counts_past_the_end_are_errors() {
+        let bytes: Vec<u8> = binary("binary_little_endian", false);
+        let at: usize = bytes.windows(8).position(|w| w == b"vertex 3").unwrap() + 8;
+        let huge: Vec<u8> = [&bytes[..at], b"0000000000", &bytes[at..]].concat();
+        assert_eq!(invalid(&huge), "truncated binary PLY");
+
+        // An element with nothing in it would otherwise loop without reading
+        let header = "ply\nformat binary_little_endian 1.0\nelement nothing 18446744073709551615\n\
+                      element vertex 1\nproperty float x\nproperty float y\nproperty float z\n\
+                      end_header\n";
+        assert!(invalid(header.as_bytes()).contains("no properties"));
+
+        let ascii = "ply\nformat ascii 1.0\nelement vertex 5\nproperty float x\n\
+                     property float y\nproperty float z\nend_header\n0 0 0\n";
+        invalid(ascii.as_bytes());
+    }
+
+    #[test]
+    fn header_errors() {
+        let line_of = |source: &str| match parse(source.as_bytes()) {
+            Err(LoadError::Parse { line, .. }) => line,
+            result => panic!("expected a parse error, got {:?}", result.map(|_| ())),
+        };
+        assert_eq!(line_of("obj\nend_header\n"), 1);
+        assert_eq!(line_of("ply\nformat weird 1.0\nend_header\n"), 2);
+        assert_eq!(
+            line_of("ply\nformat ascii 1.0\nelement vertex -1\nend_header\n"),
+            3
+        );
+        assert_eq!(
+            line_of("ply\nformat ascii 1.0\nproperty float x\nend_header\n"),
+            3
+        );
+        invalid(b"ply\nformat ascii 1.0\n");
+    }
+}
@@ -0,0 +1,253 @@
+use super::{parse_error, parse_vec3, LoadError};
+use crate::mesh::Mesh;
+use crate::vector::Vec3;
+use std::fs;
+use std::path::Path;
+
+const HEADER_SIZE: usize = 80;
+const FACET_SIZE: usize = 50; // normal, three vertices and a 2 byte attribute
+
+// Reads an ASCII or binary .stl file, centered on the origin and scaled to fit a 2 unit cube
+pub fn load(path: &Path) -> Result<Mesh, LoadError> {
+    let bytes: Vec<u8> = fs::read(path)?;
+    Ok(parse(&bytes)?.fitted(2.0))
+}
+
+pub fn parse(bytes: &[u8]) -> Result<Mesh, LoadError> {
+    if is_binary(bytes) {
+        return parse_binary(bytes);
+    }
+    match std::str::from_utf8(bytes) {
+        Ok(source) => parse_ascii(source),
+        // Not text either, so most likely a binary file that was cut short
+        Err(_) => parse_binary(bytes),
+    }
+}
+
+// Binary files can also start with "solid", so trust the size their facet count implies
+fn is_binary(bytes: &[u8]) -> bool {
+    match read_u32(bytes, HEADER_SIZE) {
+        Some(count) => HEADER_SIZE + 4 + count as usize * FACET_SIZE == bytes.len(),
+        None => false,
+    }
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> Option<u32> {
+    let word: [u8; 4] = bytes.get(offset..offset + 4)?.try_into().ok()?;
+    Some(u32::from_le_bytes(word))
+}
+
+fn read_vec3(bytes: &[u8], offset: usize) -> Option<Vec3> {
+    let component = |i: usize| -> Option<f64> {
+        let word: [u8; 4] = bytes
+            .get(offset + i * 4..offset + i * 4 + 4)?
+            .try_into()
+            .ok()?;
+        Some(f32::from_le_bytes(word) as f64)
+    };
+    Some(Vec3 {
+        x: component(0)?,
+        y: component(1)?,
+        z: component(2)?,
+    })
+}
+
+fn parse_binary(bytes: &[u8]) -> Result<Mesh, LoadError> {
+    let truncated = || LoadError::Invalid("truncated binary STL".to_string());
+    let count: usize = read_u32(bytes, HEADER_SIZE).ok_or_else(truncated)? as usize;
+
+    // The count comes from the file, so never reserve more than the data could hold
+    let mut facets: Vec<(Vec3, [Vec3; 3])> =
+        Vec::with_capacity(count.min(bytes.len() / FACET_SIZE));
+    for facet in 0..count {
+        let offset: usize = HEADER_SIZE + 4 + facet * FACET_SIZE;
+        let normal: Vec3 = read_vec3(bytes, offset).ok_or_else(truncated)?;
+        let a: Vec3 = read_vec3(bytes, offset + 12).ok_or_else(truncated)?;
+        let b: Vec3 = read_vec3(bytes, offset + 24).ok_or_else(truncated)?;
+        let c: Vec3 = read_vec3(bytes, offset + 36).ok_or_else(truncated)?;
+        facets.push((normal, [a, b, c]));
+    }
+    build_mesh(facets)
+}
+
+fn parse_ascii(source: &str) -> Result<Mesh, LoadError> {
+    let mut facets: Vec<(Vec3, [Vec3; 3])> = Vec::new();
+    let mut normal: Vec3 = Vec3 {
+        x: 0.0,
+        y: 0.0,
+        z: 0.0,
+    };
+    let mut corners: Vec<Vec3> = Vec::new();
+
+    for (number, line) in source.lines().enumerate() {
+        let line_number = number + 1;
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+
+        match tokens.first() {
+            Some(&"facet") => {
+                if tokens.get(1) != Some(&"normal") {
+                    return Err(parse_error(line_number, "expected 'facet normal'"));
+                }
+                normal = parse_vec3(&tokens[2..], line_number)?;
+                corners.clear();
+            }
+            Some(&"vertex") => corners.push(parse_vec3(&tokens[1..], line_number)?),
+            Some(&"endfacet") => {
+                if corners.len() != 3 {
+                    return Err(parse_error(
+                        line_number,
+                        &format!("facet has {} vertices, expected 3", corners.len()),
+                    ));
+                }
+                facets.push((normal, [corners[0], corners[1], corners[2]]));
+            }
+            // solid, outer loop, endloop and endsolid carry nothing we need
+            _ => {}
+        }
+    }
+    build_mesh(facets)
+}
+
+// Facets keep their own vertices so each can carry its facet normal
+fn build_mesh(facets: Vec<(Vec3, [Vec3; 3])>) -> Result<Mesh, LoadError> {
+    if facets.is_empty() {
+        return Err(LoadError::Invalid("STL file has no facets".to_string()));
+    }
+
+    let mut vertices: Vec<Vec3> = Vec::with_capacity(facets.len() * 3);
+    let mut normals: Vec<Vec3> = Vec::with_capacity(facets.len() * 3);
+    let mut indices: Vec<[usize; 3]> = Vec::with_capacity(facets.len());
+
+    for (normal, [a, b, c]) in facets {
+        // Plenty of exporters write zero normals, so fall back to the winding order
+        let mut normal: Vec3 = normal;
+        if Vec3::dot(&normal, &normal) < f64::EPSILON {
+            normal = Vec3::cross(&(b - a), &(c - a));
+        }
+        let length: f64 = normal.length();
+        if length > 0.0 {
+            normal = normal / length;
+        }
+
+        indices.push([vertices.len(), vertices.len() + 1, vertices.len() + 2]);
+        vertices.extend([a, b, c]);
+        normals.extend([normal, normal, normal]);
+    }
+
+    let mut mesh = Mesh::new(vertices, indices);
+    mesh.set_normals(normals);
+    Ok(mesh)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Binary STL with the given header text and triangles, normals left zero
+    fn binary(header: &str, triangles: &[[[f32; 3]; 3]]) -> Vec<u8> {
+        let mut bytes: Vec<u8> = vec![0; HEADER_SIZE];
+        bytes[..header.len()].copy_from_slice(header.as_bytes());
+        bytes.extend((triangles.len() as u32).to_le_bytes());
+        for triangle in triangles {
+            bytes.extend([0u8; 12]);
+            for corner in triangle {
+                for component in corner {
+                    bytes.extend(component.to_le_bytes());
+                }
+            }
+            bytes.extend([0u8; 2]);
+        }
+        bytes
+    }
+
+    const TRIANGLE: [[f32; 3]; 3] = [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]];
+
+    fn is_invalid(result: Result<Mesh, LoadError>) -> bool {
+        matches!(result, Err(LoadError::Invalid(_)))
+    }
+
+    #[test]
+    fn binary_facets_get_their_winding_normal() {
+        let mesh: Mesh = parse(&binary("exported", &[TRIANGLE, TRIANGLE])).unwrap();
+        assert_eq!(mesh.triangle_count(), 2);
+        assert_eq!(mesh.vertices()[1].x, 1.0);
+        assert_eq!(
+            mesh.normals().unwrap()[0],
+            Vec3 {
+                x: 0.0,
+                y: 0.0,
+                z: 1.0
+            }
+        );
+    }
+
+    #[test]
+    fn binary_starting_with_solid_is_still_binary() {
+        let bytes: Vec<u8> = binary("solid but actually binary", &[TRIANGLE]);
+        assert_eq!(parse(&bytes).unwrap().triangle_count(), 1);
+
+        // Cut short it's neither a good binary nor text
+        let mut bytes: Vec<u8> = binary("solid but actually binary", &[TRIANGLE; 3]);
+        bytes[HEADER_SIZE + 20] = 0xff; // not UTF-8
+        bytes.truncate(bytes.len() - 10);
+        assert!(is_invalid(parse(&bytes)));
+    }
+
+    #[test]
+    fn truncated_binary_is_an_error() {
+        let bytes: Vec<u8> = binary("", &[TRIANGLE; 2]);
+        for length in [0, 10, HEADER_SIZE + 2, HEADER_SIZE + 4 + FACET_SIZE + 7] {
+            let mut cut: Vec<u8> = bytes[..length].to_vec();
+            cut.extend([0xff]); // keeps it from passing as text
+            assert!(parse(&cut).is_err(), "cut at {}", length);
+        }
+    }
+
+    #[test]
+    fn facet_count_past_the_end_is_an_error() {
+        let mut bytes: Vec<u8> = binary("", &[TRIANGLE]);
+        bytes[HEADER_SIZE..HEADER_SIZE + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+        bytes[0] = 0xff;
+        assert!(is_invalid(parse(&bytes)));
+    }
+
+    #[test]
+    fn ascii_facets() {
+        let source = "solid square
+  facet normal 0 0 1
+    outer loop
+      vertex 0 0 0
+      vertex 1 0 0
+      vertex 1 1 0
+    endloop
+  endfacet
+  facet normal 0 0 0
+    outer loop
+      vertex 0 0 0
+      vertex 1 1 0
+      vertex 0 1 0
+    endloop
+  endfacet
+endsolid square
+";
+        let mesh: Mesh = parse(source.as_bytes()).unwrap();
+        assert_eq!(mesh.triangle_count(), 2);
+        assert_eq!(mesh.normals().unwrap()[3], mesh.normals().unwrap()[0]);
+    }
+
+    #[test]
+    fn garbage_ascii_is_an_error() {
+        let line_of = |source: &str| match parse(source.as_bytes()) {
+            Err(LoadError::Parse { line, .. }) => line,
+            result => panic!("expected a parse error, got {:?}", result.map(|_| ())),
+        };
+        assert_eq!(line_of("solid x\nfacet\n"), 2);
+        assert_eq!(line_of("solid x\nfacet normal 0 0 1\nvertex 1 2\n"), 3);
+        assert_eq!(line_of("facet normal 0 0 1\nvertex 0 0 0\nendfacet\n"), 3);
+        assert_eq!(line_of("facet normal a b c\n"), 1);
+
+        assert!(is_invalid(parse(b"")));
+        assert!(is_invalid(parse(b"solid nothing\nendsolid\n")));
+        assert!(is_invalid(parse(b"just some words, no facets")));
+    }
+}
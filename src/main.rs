@@ -1,26 +1,62 @@
 // #![allow(dead_code)]
-use std::thread;
-use std::time::Duration;
-
+mod aabb;
+mod bvh;
 mod camera;
+mod clock;
+mod controller;
+mod csg;
+mod fractal;
+mod input_handler;
+mod instance;
+mod loader;
+mod matrix;
+mod mesh;
 mod object;
 mod ray;
+mod roots;
 mod rotation;
+mod scene;
+mod sdf;
 mod terminal;
+mod transform;
 mod vector;
 
+use clock::FrameClock;
+use controller::{FlyController, OrbitController};
+use csg::Csg;
+use input_handler::{InputEvent, TerminalInput};
+use instance::Geometry;
 use object::{Object, ObjectTrait};
+use scene::Scene;
+use sdf::{Sdf, SdfObject};
+use std::env;
+use std::path::Path;
+use std::process;
+use std::rc::Rc;
+use transform::Transform;
 use vector::Vec3;
 
-fn ray_hit<'a>(r: &'a ray::Ray, object: &'a Object) -> &'a str {
-    if object.hit(r) {
-        "#"
-    } else {
-        "."
+// Brightness ramp, darkest first
+const SHADES: &[u8] = b".:-=+*#%@";
+const LIGHT_DIR: Vec3 = Vec3 {
+    x: -0.4,
+    y: 0.8,
+    z: 0.45,
+};
+
+fn ray_hit(r: &ray::Ray, scene: &Scene) -> char {
+    match scene.intersect(r) {
+        Some(hit) => {
+            // Surfaces are lit from both sides, and less so in crevices
+            let brightness: f64 =
+                libm::fabs(Vec3::dot(&hit.normal, &LIGHT_DIR)).min(1.0) * (1.0 - hit.occlusion);
+            SHADES[(brightness * (SHADES.len() - 1) as f64) as usize] as char
+        }
+        None => ' ',
     }
 }
 
-fn draw_frame(camera: camera::Camera, object: Object) {
+fn draw_frame(camera: camera::Camera, scene: &Scene, hud: Option<String>) {
     let mut frame: String = "".to_string();
     for j in 0..camera.image_height() {
         for i in 0..camera.image_width() {
@@ -31,12 +67,15 @@ fn draw_frame(camera: camera::Camera, object: Object) {
 
             let r: ray::Ray = ray::Ray::new(camera.camera_pos(), ray_dir);
             // print!("{}", ray_hit(&r, &tri));
-            frame += ray_hit(&r, &object);
+            frame.push(ray_hit(&r, scene));
         }
         // println!("");
         frame += "\n";
     }
-    println!("{}", frame);
+    match hud {
+        Some(hud) => println!("\x1B[H{}{}", frame, hud),
+        None => println!("\x1B[H{}", frame),
+    }
 }
 
 fn main() {
@@ -48,47 +87,354 @@ fn main() {
     camera.set_stretch(camera::Stretch(0.4, 1.0));
     println!("{:?}", camera);
 
-    let euler = Vec3 {
-        x: 0.08,
-        y: 0.08,
-        z: 0.0,
-    };
-
-    let a = Vec3 {
-        x: 0.5,
-        y: 0.0,
-        z: -2.0,
-    };
-    let b = Vec3 {
-        x: -0.5,
-        y: 0.0,
+    // With no model to view, show a small demo scene. --fractals shows some
+    // animated fractals instead.
+    let path: Option<String> = env::args().nth(1);
+    let mut scene = Scene::new();
+    let mut target = Vec3 {
+        x: 0.0,
+        y: 1.0 / 3.0,
         z: -2.0,
     };
-    let c = Vec3 {
+    let mut distance: f64 = 2.0;
+    let mut demo: Option<(usize, usize, usize, usize, usize)> = None;
+    let mut fractals: Option<(usize, usize, usize)> = None;
+    match &path {
+        Some(path) if path == "--fractals" => {
+            let bulb = scene.add_sdf(
+                SdfObject::new(Sdf::new_mandelbulb(8.0, 5).scale(0.7)).move_to(Vec3 {
+                    x: -1.8,
+                    y: 0.0,
+                    z: -3.0,
+                }),
+            );
+            let sponge = scene.add_sdf(
+                SdfObject::new(Sdf::new_menger_sponge(3).scale(0.6)).move_to(Vec3 {
+                    x: 0.0,
+                    y: 0.0,
+                    z: -3.0,
+                }),
+            );
+            let tetrahedron = scene.add_sdf(
+                SdfObject::new(Sdf::new_sierpinski_tetrahedron(5).scale(0.7)).move_to(Vec3 {
+                    x: 1.8,
+                    y: 0.0,
+                    z: -3.0,
+                }),
+            );
+            scene.add(Object::new_plane(
+                &Vec3 {
+                    x: 0.0,
+                    y: -1.0,
+                    z: 0.0,
+                },
+                &Vec3 {
+                    x: 0.0,
+                    y: 1.0,
+                    z: 0.0,
+                },
+            ));
+            target = Vec3 {
+                x: 0.0,
+                y: 0.0,
+                z: -3.0,
+            };
+            distance = 3.0;
+            fractals = Some((bulb, sponge, tetrahedron));
+        }
+        Some(path) => {
+            scene = match loader::load(Path::new(path)) {
+                Ok(scene) => scene,
+                Err(err) => {
+                    eprintln!("{}: {}", path, err);
+                    process::exit(1);
+                }
+            };
+            if let Some(bounds) = scene.mesh_bounds() {
+                let size: Vec3 = bounds.max - bounds.min;
+                target = bounds.center();
+                distance = 1.5 * size.length().max(0.1);
+            }
+        }
+        None => {
+            // The spinning shapes are built round their own origins and placed
+            // by scene nodes, so turning them never bakes errors into the shapes
+            let place = |x: f64, y: f64, z: f64| Transform::identity().translate(Vec3 { x, y, z });
+            let tri = scene.add_node(
+                None,
+                place(0.0, 0.0, -2.0),
+                Some(Rc::new(Geometry::new_object(Object::new_triangle(
+                    &Vec3 {
+                        x: 0.5,
+                        y: 0.0,
+                        z: 0.0,
+                    },
+                    &Vec3 {
+                        x: -0.5,
+                        y: 0.0,
+                        z: 0.0,
+                    },
+                    &Vec3 {
+                        x: 0.0,
+                        y: 1.0,
+                        z: 0.0,
+                    },
+                )))),
+            );
+            // A cube with a bite taken out of its front corner
+            let cube = scene.add_node(
+                None,
+                place(1.2, 0.0, -2.5),
+                Some(Rc::new(Geometry::new_csg(
+                    Csg::new(Object::new_box(
+                        &Vec3 {
+                            x: -0.3,
+                            y: -0.3,
+                            z: -0.3,
+                        },
+                        &Vec3 {
+                            x: 0.3,
+                            y: 0.3,
+                            z: 0.3,
+                        },
+                    ))
+                    .difference(Csg::new(Object::new_sphere(
+                        &Vec3 {
+                            x: 0.3,
+                            y: 0.3,
+                            z: 0.3,
+                        },
+                        &0.35,
+                    ))),
+                ))),
+            );
+            let donut = scene.add_node(
+                None,
+                place(-1.3, 0.2, -2.6),
+                Some(Rc::new(Geometry::new_object(Object::new_torus(
+                    &Vec3 {
+                        x: 0.0,
+                        y: 0.0,
+                        z: 0.0,
+                    },
+                    0.45,
+                    0.18,
+                    &rotation::Quaternion::new(1.0, 0.0, 0.0, 0.0),
+                )))),
+            );
+            // A rounded block melting into a ball, which triangles can't do
+            let blob = scene.add_node(
+                None,
+                place(0.0, 0.1, -3.6),
+                Some(Rc::new(Geometry::new_sdf(SdfObject::new(
+                    Sdf::new_round_box(
+                        &Vec3 {
+                            x: 0.0,
+                            y: -0.3,
+                            z: 0.0,
+                        },
+                        &Vec3 {
+                            x: 0.4,
+                            y: 0.2,
+                            z: 0.4,
+                        },
+                        0.08,
+                    )
+                    .smooth_union(
+                        Sdf::new_sphere(
+                            &Vec3 {
+                                x: 0.0,
+                                y: 0.25,
+                                z: 0.0,
+                            },
+                            0.3,
+                        ),
+                        0.3,
+                    ),
+                )))),
+            );
+            scene.add(Object::new_plane(
+                &Vec3 {
+                    x: 0.0,
+                    y: -0.5,
+                    z: 0.0,
+                },
+                &Vec3 {
+                    x: 0.0,
+                    y: 1.0,
+                    z: 0.0,
+                },
+            ));
+            // A post standing on the floor, capped with the top half of a ball
+            scene.add_csg(
+                Csg::new(Object::new_box(
+                    &Vec3 {
+                        x: 1.3,
+                        y: -0.5,
+                        z: -4.3,
+                    },
+                    &Vec3 {
+                        x: 1.5,
+                        y: 0.3,
+                        z: -4.1,
+                    },
+                ))
+                .union(
+                    Csg::new(Object::new_sphere(
+                        &Vec3 {
+                            x: 1.4,
+                            y: 0.3,
+                            z: -4.2,
+                        },
+                        &0.3,
+                    ))
+                    .intersection(Csg::new(Object::new_box(
+                        &Vec3 {
+                            x: 1.1,
+                            y: 0.3,
+                            z: -4.5,
+                        },
+                        &Vec3 {
+                            x: 1.7,
+                            y: 0.6,
+                            z: -3.9,
+                        },
+                    ))),
+                ),
+            );
+            // Three squashed copies of one ball, carried round by their parent
+            let ball = Rc::new(Geometry::new_object(Object::new_sphere(
+                &Vec3 {
+                    x: 0.0,
+                    y: 0.0,
+                    z: 0.0,
+                },
+                &1.0,
+            )));
+            let carousel = scene.add_node(
+                None,
+                Transform::identity().translate(Vec3 {
+                    x: -1.0,
+                    y: 0.8,
+                    z: -3.4,
+                }),
+                None,
+            );
+            for i in 0..3 {
+                let angle: f64 = i as f64 * 2.0 * std::f64::consts::PI / 3.0;
+                scene.add_node(
+                    Some(carousel),
+                    Transform::new(
+                        Vec3 {
+                            x: 0.45 * libm::cos(angle),
+                            y: 0.0,
+                            z: 0.45 * libm::sin(angle),
+                        },
+                        rotation::Quaternion::new(1.0, 0.0, 0.0, 0.0),
+                        Vec3 {
+                            x: 0.12,
+                            y: 0.25,
+                            z: 0.12,
+                        },
+                    ),
+                    Some(ball.clone()),
+                );
+            }
+            demo = Some((tri, cube, donut, blob, carousel));
+        }
+    }
+    // Places the nodes, and builds the BVH over them and everything else
+    scene.update_instances();
+
+    let _session = terminal::TerminalSession::new();
+    let mut input = TerminalInput::new();
+    let mut fly = FlyController::new(&camera);
+    let mut orbit = OrbitController::new(target, distance, terminal.columns(), terminal.lines());
+    let mut orbiting = true;
+
+    // Start from the camera the file asked for, orbiting whatever it looks at
+    if let Some(view) = scene.view {
+        if let Some(yfov) = view.yfov {
+            camera.set_focal_length(1.0 / libm::tan(yfov / 2.0));
+        }
+        let offset: Vec3 = target - view.position;
+        let view_distance: f64 = offset.length().max(0.1);
+        let forward: Vec3 = view.orientation.rotate(&Vec3 {
+            x: 0.0,
+            y: 0.0,
+            z: -1.0,
+        });
+        orbit.set_target(view.position + forward * view_distance);
+        orbit.set_distance(view_distance);
+        orbit.set_orientation(view.orientation);
+    }
+
+    let spin = Vec3 {
         x: 0.0,
-        y: 1.0,
-        z: -2.0,
+        y: 1.5,
+        z: 0.0,
+    }; // radians per second
+    let tumble = Vec3 {
+        x: 1.0,
+        y: 0.0,
+        z: 0.6,
     };
-    let mut tri = Object::new_triangle(&a, &b, &c);
+    let mut clock = FrameClock::new(30.0);
+    let mut show_hud = true;
 
     loop {
-        draw_frame(camera, tri);
+        let dt: f64 = clock.tick();
+
+        let events: Vec<InputEvent> = input.poll();
+        if events.contains(&InputEvent::Keyboard('\x1B')) {
+            break;
+        }
+        if events.contains(&InputEvent::Keyboard('\t')) {
+            orbiting = !orbiting;
+            fly.sync(&camera);
+        }
+        if events.contains(&InputEvent::Keyboard('h')) {
+            show_hud = !show_hud;
+        }
+        if orbiting {
+            orbit.update(&mut camera, &events);
+        } else {
+            fly.update(&mut camera, &events, dt);
+        }
+
+        if let Some((tri, cube, donut, blob, carousel)) = demo {
+            for (node, turn) in [
+                (tri, spin),
+                (cube, spin),
+                (donut, tumble),
+                (blob, spin),
+                (carousel, spin),
+            ] {
+                scene.nodes[node].transform = scene.nodes[node].transform.rotate(turn * dt);
+            }
+            scene.update_instances();
+        }
+
+        if let Some((bulb, sponge, tetrahedron)) = fractals {
+            // The bulb breathes between powers 3 and 9, and the sponge goes
+            // through its first few levels
+            let time: f64 = clock.time();
+            scene.sdfs[bulb]
+                .set_shape(Sdf::new_mandelbulb(6.0 + 3.0 * libm::sin(time / 2.0), 5).scale(0.7));
+            scene.sdfs[sponge]
+                .set_shape(Sdf::new_menger_sponge(1 + (time / 2.0) as usize % 4).scale(0.6));
+            scene.sdfs[bulb] = scene.sdfs[bulb].rotate_around_center(spin * dt / 3.0);
+            scene.sdfs[sponge] = scene.sdfs[sponge].rotate_around_center(tumble * dt / 3.0);
+            scene.sdfs[tetrahedron] = scene.sdfs[tetrahedron].rotate_around_center(spin * dt / 2.0);
+        }
 
-        // tri = tri.rotate_around_point(
-        //     euler,
-        //     Vec3 {
-        //         x: 0.0,
-        //         y: 0.0,
-        //         z: -2.0,
-        //     },
-        // );
-        camera.rotate_around_center(euler);
-        println!(
-            "viewport_u: {:?}\nviewport_v: {:?}",
-            camera.viewport_u, camera.viewport_v
-        );
-        println!("triangle: {:?}", tri);
-        thread::sleep(Duration::from_millis(50));
-        // print!("\x1Bc");
+        let hud: Option<String> = if show_hud {
+            let (width, height) = (camera.image_width(), camera.image_height());
+            Some(clock.hud((width * height) as usize, width, height))
+        } else {
+            None
+        };
+        draw_frame(camera, &scene, hud);
+        clock.end_frame();
     }
 }
@@ -0,0 +1,503 @@
+use crate::rotation::Quaternion;
+use crate::vector::Vec3;
+use libm::{sqrt, tan};
+use std::ops;
+
+// Row major, acting on column vectors
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Mat3 {
+    pub rows: [[f64; 3]; 3],
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Mat4 {
+    pub rows: [[f64; 4]; 4],
+}
+
+impl Mat3 {
+    pub fn new(rows: [[f64; 3]; 3]) -> Mat3 {
+        Mat3 { rows }
+    }
+    pub fn identity() -> Mat3 {
+        Mat3::new([[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]])
+    }
+    pub fn from_columns(x: &Vec3, y: &Vec3, z: &Vec3) -> Mat3 {
+        Mat3::new([[x.x, y.x, z.x], [x.y, y.y, z.y], [x.z, y.z, z.z]])
+    }
+    // The columns are where the rotation takes each axis
+    pub fn from_quaternion(q: &Quaternion) -> Mat3 {
+        Mat3::from_columns(
+            &q.rotate(&Vec3 {
+                x: 1.0,
+                y: 0.0,
+                z: 0.0,
+            }),
+            &q.rotate(&Vec3 {
+                x: 0.0,
+                y: 1.0,
+                z: 0.0,
+            }),
+            &q.rotate(&Vec3 {
+                x: 0.0,
+                y: 0.0,
+                z: 1.0,
+            }),
+        )
+    }
+
+    pub fn column(&self, i: usize) -> Vec3 {
+        Vec3 {
+            x: self.rows[0][i],
+            y: self.rows[1][i],
+            z: self.rows[2][i],
+        }
+    }
+
+    // Only meaningful for rotations. Shepperd's method, which divides by the
+    // largest of the four components to stay accurate.
+    pub fn to_quaternion(self) -> Quaternion {
+        let r = |row: usize, column: usize| self.rows[row][column];
+        let trace: f64 = r(0, 0) + r(1, 1) + r(2, 2);
+        if trace > 0.0 {
+            let s: f64 = sqrt(trace + 1.0) * 2.0;
+            Quaternion::new(
+                s / 4.0,
+                (r(2, 1) - r(1, 2)) / s,
+                (r(0, 2) - r(2, 0)) / s,
+                (r(1, 0) - r(0, 1)) / s,
+            )
+        } else if r(0, 0) > r(1, 1) && r(0, 0) > r(2, 2) {
+            let s: f64 = sqrt(1.0 + r(0, 0) - r(1, 1) - r(2, 2)) * 2.0;
+            Quaternion::new(
+                (r(2, 1) - r(1, 2)) / s,
+                s / 4.0,
+                (r(0, 1) + r(1, 0)) / s,
+                (r(0, 2) + r(2, 0)) / s,
+            )
+        } else if r(1, 1) > r(2, 2) {
+            let s: f64 = sqrt(1.0 + r(1, 1) - r(0, 0) - r(2, 2)) * 2.0;
+            Quaternion::new(
+                (r(0, 2) - r(2, 0)) / s,
+                (r(0, 1) + r(1, 0)) / s,
+                s / 4.0,
+                (r(1, 2) + r(2, 1)) / s,
+            )
+        } else {
+            let s: f64 = sqrt(1.0 + r(2, 2) - r(0, 0) - r(1, 1)) * 2.0;
+            Quaternion::new(
+                (r(1, 0) - r(0, 1)) / s,
+                (r(0, 2) + r(2, 0)) / s,
+                (r(1, 2) + r(2, 1)) / s,
+                s / 4.0,
+            )
+        }
+    }
+
+    pub fn transpose(&self) -> Mat3 {
+        let mut transposed: Mat3 = *self;
+        for i in 0..3 {
+            for j in 0..3 {
+                transposed.rows[i][j] = self.rows[j][i];
+            }
+        }
+        transposed
+    }
+
+    pub fn determinant(&self) -> f64 {
+        Vec3::dot(
+            &self.column(0),
+            &Vec3::cross(&self.column(1), &self.column(2)),
+        )
+    }
+
+    // Transpose of the cofactors, the inverse times the determinant. Unlike the
+    // inverse it exists for singular matrices too.
+    pub fn adjugate(&self) -> Mat3 {
+        let (x, y, z) = (self.column(0), self.column(1), self.column(2));
+        Mat3::from_columns(
+            &Vec3::cross(&y, &z),
+            &Vec3::cross(&z, &x),
+            &Vec3::cross(&x, &y),
+        )
+        .transpose()
+    }
+
+    // None if the matrix is singular
+    pub fn inverse(&self) -> Option<Mat3> {
+        let determinant: f64 = self.determinant();
+        if determinant == 0.0 {
+            return None;
+        }
+        let mut inverse: Mat3 = self.adjugate();
+        for row in inverse.rows.iter_mut() {
+            for value in row.iter_mut() {
+                *value /= determinant;
+            }
+        }
+        Some(inverse)
+    }
+}
+
+impl Mat4 {
+    pub fn new(rows: [[f64; 4]; 4]) -> Mat4 {
+        Mat4 { rows }
+    }
+    pub fn identity() -> Mat4 {
+        Mat4::from_mat3(&Mat3::identity())
+    }
+    // With no translation
+    pub fn from_mat3(m: &Mat3) -> Mat4 {
+        let r = m.rows;
+        Mat4::new([
+            [r[0][0], r[0][1], r[0][2], 0.0],
+            [r[1][0], r[1][1], r[1][2], 0.0],
+            [r[2][0], r[2][1], r[2][2], 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+    pub fn from_translation(v: &Vec3) -> Mat4 {
+        let mut m: Mat4 = Mat4::identity();
+        m.rows[0][3] = v.x;
+        m.rows[1][3] = v.y;
+        m.rows[2][3] = v.z;
+        m
+    }
+    pub fn from_scale(v: &Vec3) -> Mat4 {
+        Mat4::from_mat3(&Mat3::new([
+            [v.x, 0.0, 0.0],
+            [0.0, v.y, 0.0],
+            [0.0, 0.0, v.z],
+        ]))
+    }
+    pub fn from_quaternion(q: &Quaternion) -> Mat4 {
+        Mat4::from_mat3(&Mat3::from_quaternion(q))
+    }
+    // Scales, then rotates, then translates
+    pub fn from_trs(translation: &Vec3, rotation: &Quaternion, scale: &Vec3) -> Mat4 {
+        Mat4::from_translation(translation)
+            * Mat4::from_quaternion(rotation)
+            * Mat4::from_scale(scale)
+    }
+
+    // Takes the world into the space of a camera at eye looking at target,
+    // which looks down -z with +y up like the rest of the renderer
+    pub fn look_at(eye: &Vec3, target: &Vec3, up: &Vec3) -> Mat4 {
+        let forward: Vec3 = (*target - *eye).normalize();
+        let right: Vec3 = Vec3::cross(&forward, up).normalize();
+        let up: Vec3 = Vec3::cross(&right, &forward);
+        Mat4::new([
+            [right.x, right.y, right.z, -Vec3::dot(&right, eye)],
+            [up.x, up.y, up.z, -Vec3::dot(&up, eye)],
+            [-forward.x, -forward.y, -forward.z, Vec3::dot(&forward, eye)],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+    // Maps the view frustum into the cube from -1 to 1, OpenGL style
+    pub fn perspective(yfov: f64, aspect_ratio: f64, near: f64, far: f64) -> Mat4 {
+        let f: f64 = 1.0 / tan(yfov / 2.0);
+        Mat4::new([
+            [f / aspect_ratio, 0.0, 0.0, 0.0],
+            [0.0, f, 0.0, 0.0],
+            [
+                0.0,
+                0.0,
+                (far + near) / (near - far),
+                2.0 * far * near / (near - far),
+            ],
+            [0.0, 0.0, -1.0, 0.0],
+        ])
+    }
+
+    pub fn upper_left(&self) -> Mat3 {
+        let r = self.rows;
+        Mat3::new([
+            [r[0][0], r[0][1], r[0][2]],
+            [r[1][0], r[1][1], r[1][2]],
+            [r[2][0], r[2][1], r[2][2]],
+        ])
+    }
+    pub fn translation(&self) -> Vec3 {
+        Vec3 {
+            x: self.rows[0][3],
+            y: self.rows[1][3],
+            z: self.rows[2][3],
+        }
+    }
+    // Only meaningful when the upper left is a rotation
+    pub fn to_quaternion(self) -> Quaternion {
+        self.upper_left().to_quaternion()
+    }
+
+    pub fn transpose(&self) -> Mat4 {
+        let mut transposed: Mat4 = *self;
+        for i in 0..4 {
+            for j in 0..4 {
+                transposed.rows[i][j] = self.rows[j][i];
+            }
+        }
+        transposed
+    }
+
+    pub fn determinant(&self) -> f64 {
+        let (s, c) = self.minors();
+        s[0] * c[5] - s[1] * c[4] + s[2] * c[3] + s[3] * c[2] - s[4] * c[1] + s[5] * c[0]
+    }
+
+    // None if the matrix is singular
+    pub fn inverse(&self) -> Option<Mat4> {
+        let a = self.rows;
+        let (s, c) = self.minors();
+        let determinant: f64 =
+            s[0] * c[5] - s[1] * c[4] + s[2] * c[3] + s[3] * c[2] - s[4] * c[1] + s[5] * c[0];
+        if determinant == 0.0 {
+            return None;
+        }
+        let adjugate: [[f64; 4]; 4] = [
+            [
+                a[1][1] * c[5] - a[1][2] * c[4] + a[1][3] * c[3],
+                -a[0][1] * c[5] + a[0][2] * c[4] - a[0][3] * c[3],
+                a[3][1] * s[5] - a[3][2] * s[4] + a[3][3] * s[3],
+                -a[2][1] * s[5] + a[2][2] * s[4] - a[2][3] * s[3],
+            ],
+            [
+                -a[1][0] * c[5] + a[1][2] * c[2] - a[1][3] * c[1],
+                a[0][0] * c[5] - a[0][2] * c[2] + a[0][3] * c[1],
+                -a[3][0] * s[5] + a[3][2] * s[2] - a[3][3] * s[1],
+                a[2][0] * s[5] - a[2][2] * s[2] + a[2][3] * s[1],
+            ],
+            [
+                a[1][0] * c[4] - a[1][1] * c[2] + a[1][3] * c[0],
+                -a[0][0] * c[4] + a[0][1] * c[2] - a[0][3] * c[0],
+                a[3][0] * s[4] - a[3][1] * s[2] + a[3][3] * s[0],
+                -a[2][0] * s[4] + a[2][1] * s[2] - a[2][3] * s[0],
+            ],
+            [
+                -a[1][0] * c[3] + a[1][1] * c[1] - a[1][2] * c[0],
+                a[0][0] * c[3] - a[0][1] * c[1] + a[0][2] * c[0],
+                -a[3][0] * s[3] + a[3][1] * s[1] - a[3][2] * s[0],
+                a[2][0] * s[3] - a[2][1] * s[1] + a[2][2] * s[0],
+            ],
+        ];
+        Some(Mat4::new(
+            adjugate.map(|row| row.map(|value| value / determinant)),
+        ))
+    }
+
+    // 2x2 determinants of the top two rows (s) and the bottom two (c), which
+    // the determinant and inverse are built from
+    fn minors(&self) -> ([f64; 6], [f64; 6]) {
+        let a = self.rows;
+        let minor =
+            |r0: usize, r1: usize, i: usize, j: usize| a[r0][i] * a[r1][j] - a[r1][i] * a[r0][j];
+        (
+            [
+                minor(0, 1, 0, 1),
+                minor(0, 1, 0, 2),
+                minor(0, 1, 0, 3),
+                minor(0, 1, 1, 2),
+                minor(0, 1, 1, 3),
+                minor(0, 1, 2, 3),
+            ],
+            [
+                minor(2, 3, 0, 1),
+                minor(2, 3, 0, 2),
+                minor(2, 3, 0, 3),
+                minor(2, 3, 1, 2),
+                minor(2, 3, 1, 3),
+                minor(2, 3, 2, 3),
+            ],
+        )
+    }
+
+    // Divides by w, so projections work too
+    pub fn transform_point(&self, p: &Vec3) -> Vec3 {
+        let m = self.rows;
+        let w: f64 = m[3][0] * p.x + m[3][1] * p.y + m[3][2] * p.z + m[3][3];
+        Vec3 {
+            x: m[0][0] * p.x + m[0][1] * p.y + m[0][2] * p.z + m[0][3],
+            y: m[1][0] * p.x + m[1][1] * p.y + m[1][2] * p.z + m[1][3],
+            z: m[2][0] * p.x + m[2][1] * p.y + m[2][2] * p.z + m[2][3],
+        } / w
+    }
+    // Directions ignore the translation
+    pub fn transform_vector(&self, v: &Vec3) -> Vec3 {
+        self.upper_left() * *v
+    }
+}
+
+// a * b applies b first
+impl ops::Mul<Mat3> for Mat3 {
+    type Output = Mat3;
+
+    fn mul(self, rhs: Mat3) -> Mat3 {
+        let mut product: Mat3 = Mat3::new([[0.0; 3]; 3]);
+        for i in 0..3 {
+            for j in 0..3 {
+                product.rows[i][j] = (0..3).map(|k| self.rows[i][k] * rhs.rows[k][j]).sum();
+            }
+        }
+        product
+    }
+}
+
+impl ops::Mul<Vec3> for Mat3 {
+    type Output = Vec3;
+
+    fn mul(self, rhs: Vec3) -> Vec3 {
+        let m = self.rows;
+        Vec3 {
+            x: m[0][0] * rhs.x + m[0][1] * rhs.y + m[0][2] * rhs.z,
+            y: m[1][0] * rhs.x + m[1][1] * rhs.y + m[1][2] * rhs.z,
+            z: m[2][0] * rhs.x + m[2][1] * rhs.y + m[2][2] * rhs.z,
+        }
+    }
+}
+
+impl ops::Mul<Mat4> for Mat4 {
+    type Output = Mat4;
+
+    fn mul(self, rhs: Mat4) -> Mat4 {
+        let mut product: Mat4 = Mat4::new([[0.0; 4]; 4]);
+        for i in 0..4 {
+            for j in 0..4 {
+                product.rows[i][j] = (0..4).map(|k| self.rows[i][k] * rhs.rows[k][j]).sum();
+            }
+        }
+        product
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64::consts::{FRAC_PI_2, PI};
+
+    fn point(x: f64, y: f64, z: f64) -> Vec3 {
+        Vec3 { x, y, z }
+    }
+
+    fn assert_near(a: &Mat4, b: &Mat4) {
+        for (row_a, row_b) in a.rows.iter().zip(b.rows.iter()) {
+            for (x, y) in row_a.iter().zip(row_b.iter()) {
+                assert!(libm::fabs(x - y) < 1e-9, "{:?} != {:?}", a, b);
+            }
+        }
+    }
+
+    fn assert_point(a: Vec3, b: Vec3) {
+        assert!(a.approx_eq(&b, 1e-9), "{:?} != {:?}", a, b);
+    }
+
+    // Nothing special about it, and a projective bottom row
+    fn messy() -> Mat4 {
+        Mat4::new([
+            [2.0, -1.0, 0.5, 3.0],
+            [0.0, 1.5, -2.0, 1.0],
+            [1.0, 0.25, 3.0, -4.0],
+            [0.5, 0.0, -1.0, 2.0],
+        ])
+    }
+
+    #[test]
+    fn determinant() {
+        let diagonal: Mat4 = Mat4::from_trs(
+            &point(5.0, 6.0, 7.0),
+            &Quaternion::new(1.0, 0.0, 0.0, 0.0),
+            &point(2.0, 3.0, 4.0),
+        );
+        assert!(libm::fabs(diagonal.determinant() - 24.0) < 1e-9);
+
+        // Swapping two rows flips the sign
+        let mut swapped: Mat4 = messy();
+        swapped.rows.swap(0, 2);
+        assert!(libm::fabs(swapped.determinant() + messy().determinant()) < 1e-9);
+        // Worked out by cofactor expansion
+        assert!(libm::fabs(messy().determinant() + 2.0625) < 1e-9);
+        assert!(libm::fabs(messy().upper_left().determinant() - 11.25) < 1e-9);
+    }
+
+    #[test]
+    fn inverse() {
+        let m: Mat4 = messy();
+        let inverse: Mat4 = m.inverse().unwrap();
+        assert_near(&(m * inverse), &Mat4::identity());
+        assert_near(&(inverse * m), &Mat4::identity());
+        assert!(libm::fabs(m.determinant() * inverse.determinant() - 1.0) < 1e-9);
+
+        let m3: Mat3 = m.upper_left();
+        let product: Mat3 = m3 * m3.inverse().unwrap();
+        assert_near(&Mat4::from_mat3(&product), &Mat4::identity());
+
+        // A zero scale has nothing to undo it
+        let flat: Mat4 = Mat4::from_scale(&point(1.0, 0.0, 1.0));
+        assert!(flat.inverse().is_none());
+        assert!(flat.upper_left().inverse().is_none());
+    }
+
+    #[test]
+    fn look_at_puts_the_target_down_negative_z() {
+        let eye: Vec3 = point(3.0, 1.0, 0.0);
+        let view: Mat4 = Mat4::look_at(&eye, &point(0.0, 1.0, 0.0), &point(0.0, 1.0, 0.0));
+        assert_point(view.transform_point(&eye), point(0.0, 0.0, 0.0));
+        assert_point(
+            view.transform_point(&point(0.0, 1.0, 0.0)),
+            point(0.0, 0.0, -3.0),
+        );
+        assert_point(
+            view.transform_point(&point(3.0, 2.0, 0.0)),
+            point(0.0, 1.0, 0.0),
+        );
+        // Looking down -x, so -z is on the right
+        assert_point(
+            view.transform_point(&point(3.0, 1.0, -1.0)),
+            point(1.0, 0.0, 0.0),
+        );
+        assert!(libm::fabs(view.determinant() - 1.0) < 1e-9);
+    }
+
+    #[test]
+    fn perspective_maps_the_frustum_to_a_cube() {
+        let (near, far, aspect_ratio) = (0.5, 20.0, 2.0);
+        let projection: Mat4 = Mat4::perspective(FRAC_PI_2, aspect_ratio, near, far);
+        assert_point(
+            projection.transform_point(&point(0.0, 0.0, -near)),
+            point(0.0, 0.0, -1.0),
+        );
+        assert_point(
+            projection.transform_point(&point(0.0, 0.0, -far)),
+            point(0.0, 0.0, 1.0),
+        );
+        // A 90 degree field of view reaches as high as it is far, and twice as wide
+        assert_point(
+            projection.transform_point(&point(-2.0 * far, far, -far)),
+            point(-1.0, 1.0, 1.0),
+        );
+    }
+
+    #[test]
+    fn quaternion_round_trip() {
+        // Half turns take each of Shepperd's branches
+        let axes: [Vec3; 4] = [
+            point(1.0, 0.0, 0.0),
+            point(0.0, 1.0, 0.0),
+            point(0.0, 0.0, 1.0),
+            point(1.0, -2.0, 0.5),
+        ];
+        for axis in axes {
+            for angle in [0.0, 0.3, FRAC_PI_2, 2.5, PI] {
+                let q: Quaternion = Quaternion::new_from_axis_angle(axis, angle);
+                let back: Quaternion = Mat3::from_quaternion(&q).to_quaternion();
+                assert!(
+                    back.approx_eq(&q, 1e-9) || back.approx_eq(&-q, 1e-9),
+                    "{:?} came back as {:?}",
+                    q,
+                    back
+                );
+                let back: Quaternion =
+                    Mat4::from_trs(&point(1.0, 2.0, 3.0), &q, &point(1.0, 1.0, 1.0))
+                        .to_quaternion();
+                assert!(back.approx_eq(&q, 1e-9) || back.approx_eq(&-q, 1e-9));
+            }
+        }
+    }
+}
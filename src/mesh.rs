@@ -0,0 +1,204 @@
+use crate::aabb::Aabb;
+use crate::object::{triangle_intersect, Hit, Object, ObjectTrait};
+use crate::ray::Ray;
+use crate::rotation::Quaternion;
+use crate::vector::Vec3;
+
+// Indexed triangle mesh: faces refer into one shared vertex buffer
+#[derive(Debug, Clone)]
+pub struct Mesh {
+    vertices: Vec<Vec3>,
+    indices: Vec<[usize; 3]>,
+    normals: Option<Vec<Vec3>>,   // per vertex
+    uvs: Option<Vec<(f64, f64)>>, // per vertex
+    colors: Option<Vec<Vec3>>,    // per vertex, 0 to 1 per channel
+    bounds: Aabb,
+    point_radius: Option<f64>, // draw the vertices as spheres when there are no faces
+
+    name: Option<String>,
+    color: Option<Vec3>, // diffuse color, 0 to 1 per channel
+}
+
+impl Mesh {
+    pub fn new(vertices: Vec<Vec3>, indices: Vec<[usize; 3]>) -> Mesh {
+        let bounds: Aabb = Aabb::from_points(&vertices);
+        Mesh {
+            vertices,
+            indices,
+            normals: None,
+            uvs: None,
+            colors: None,
+            bounds,
+            point_radius: None,
+            name: None,
+            color: None,
+        }
+    }
+
+    pub fn set_normals(&mut self, normals: Vec<Vec3>) {
+        self.normals = Some(normals);
+    }
+    pub fn set_uvs(&mut self, uvs: Vec<(f64, f64)>) {
+        self.uvs = Some(uvs);
+    }
+    pub fn set_colors(&mut self, colors: Vec<Vec3>) {
+        self.colors = Some(colors);
+    }
+    pub fn set_point_radius(&mut self, radius: f64) {
+        self.point_radius = Some(radius);
+        self.bounds = Mesh::padded_bounds(&self.vertices, self.point_radius);
+    }
+    pub fn set_name(&mut self, name: &str) {
+        self.name = Some(name.to_string());
+    }
+    pub fn set_color(&mut self, color: Vec3) {
+        self.color = Some(color);
+    }
+
+    pub fn vertices(&self) -> &[Vec3] {
+        &self.vertices
+    }
+    pub fn indices(&self) -> &[[usize; 3]] {
+        &self.indices
+    }
+    pub fn normals(&self) -> Option<&[Vec3]> {
+        self.normals.as_deref()
+    }
+    pub fn uvs(&self) -> Option<&[(f64, f64)]> {
+        self.uvs.as_deref()
+    }
+    pub fn colors(&self) -> Option<&[Vec3]> {
+        self.colors.as_deref()
+    }
+    pub fn point_radius(&self) -> Option<f64> {
+        self.point_radius
+    }
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+    pub fn color(&self) -> Option<Vec3> {
+        self.color
+    }
+    pub fn bounds(&self) -> Aabb {
+        self.bounds
+    }
+    pub fn triangle_count(&self) -> usize {
+        self.indices.len()
+    }
+    pub fn triangle(&self, index: usize) -> (Vec3, Vec3, Vec3) {
+        let [a, b, c] = self.indices[index];
+        (self.vertices[a], self.vertices[b], self.vertices[c])
+    }
+
+    // Intersects a single face, interpolating the vertex normals and uvs if present
+    pub fn intersect_triangle(&self, r: &Ray, index: usize) -> Option<Hit> {
+        let (a, b, c) = self.triangle(index);
+        let mut hit: Hit = triangle_intersect(r, &a, &b, &c)?;
+
+        let [ia, ib, ic] = self.indices[index];
+        let (wa, wb, wc) = (1.0 - hit.u - hit.v, hit.u, hit.v);
+        if let Some(normals) = &self.normals {
+            let normal: Vec3 = normals[ia] * wa + normals[ib] * wb + normals[ic] * wc;
+            let length: f64 = normal.length();
+            if length > 0.0 {
+                hit.normal = normal / length;
+            }
+        }
+        if let Some(uvs) = &self.uvs {
+            hit.u = uvs[ia].0 * wa + uvs[ib].0 * wb + uvs[ic].0 * wc;
+            hit.v = uvs[ia].1 * wa + uvs[ib].1 * wb + uvs[ic].1 * wc;
+        }
+        Some(hit)
+    }
+
+    // Centers the mesh on the origin and scales it evenly so its longest side is `size`
+    pub fn fitted(&self, size: f64) -> Mesh {
+        let center: Vec3 = self.bounds.center();
+        let extent: Vec3 = self.bounds.max - self.bounds.min;
+        let longest: f64 = extent.x.max(extent.y).max(extent.z);
+        let scale: f64 = if longest > 0.0 { size / longest } else { 1.0 };
+        self.transformed(|vertex| (*vertex - center) * scale, |normal| *normal)
+    }
+
+    // The sphere drawn around one vertex of a point cloud
+    pub fn intersect_point(&self, r: &Ray, index: usize) -> Option<Hit> {
+        let radius: f64 = self.point_radius?;
+        Object::new_sphere(&self.vertices[index], &radius).intersect(r)
+    }
+
+    // Vertex bounds, grown to cover the point spheres if there are any
+    fn padded_bounds(vertices: &[Vec3], point_radius: Option<f64>) -> Aabb {
+        let bounds: Aabb = Aabb::from_points(vertices);
+        match point_radius {
+            Some(radius) => {
+                let padding = Vec3 {
+                    x: radius,
+                    y: radius,
+                    z: radius,
+                };
+                Aabb::new(bounds.min - padding, bounds.max + padding)
+            }
+            None => bounds,
+        }
+    }
+
+    // Applies `f` to every vertex and `g` to every normal, then refreshes the bounds
+    pub fn transformed(&self, f: impl Fn(&Vec3) -> Vec3, g: impl Fn(&Vec3) -> Vec3) -> Mesh {
+        let vertices: Vec<Vec3> = self.vertices.iter().map(f).collect();
+        Mesh {
+            bounds: Mesh::padded_bounds(&vertices, self.point_radius),
+            vertices,
+            indices: self.indices.clone(),
+            normals: self
+                .normals
+                .as_ref()
+                .map(|normals| normals.iter().map(g).collect()),
+            uvs: self.uvs.clone(),
+            colors: self.colors.clone(),
+            point_radius: self.point_radius,
+            name: self.name.clone(),
+            color: self.color,
+        }
+    }
+}
+
+impl ObjectTrait for Mesh {
+    fn intersect(&self, r: &Ray) -> Option<Hit> {
+        self.bounds.hit(r, f64::INFINITY)?;
+
+        // A mesh without faces is a point cloud
+        let points: bool = self.indices.is_empty();
+        let count: usize = if points {
+            self.vertices.len()
+        } else {
+            self.indices.len()
+        };
+        let mut closest: Option<Hit> = None;
+        for index in 0..count {
+            let hit: Option<Hit> = if points {
+                self.intersect_point(r, index)
+            } else {
+                self.intersect_triangle(r, index)
+            };
+            if let Some(hit) = hit {
+                if closest.is_none_or(|c| hit.t < c.t) {
+                    closest = Some(hit);
+                }
+            }
+        }
+        closest
+    }
+    fn move_to(&self, v: Vec3) -> Self {
+        self.transformed(|vertex| *vertex + v, |normal| *normal)
+    }
+    fn rotate_around_center(&self, euler: Vec3) -> Self {
+        self.rotate_around_point(euler, self.bounds.center())
+    }
+    fn rotate_around_point(&self, euler: Vec3, point: Vec3) -> Self {
+        let quaternion = Quaternion::euler_to_quaternion(euler);
+        self.transformed(
+            |vertex| vertex.rotate_around_point(&point, &quaternion),
+            |normal| quaternion.rotate(normal),
+        )
+    }
+}
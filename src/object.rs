@@ -1,6 +1,20 @@
+use crate::aabb::Aabb;
 use crate::ray::Ray;
+use crate::roots::{solve_quadratic, solve_quartic};
 use crate::rotation::Quaternion;
 use crate::vector::Vec3;
+use libm::{fabs, sqrt};
+use std::f64::consts::PI;
+
+#[derive(Debug, Copy, Clone)]
+pub struct Hit {
+    pub t: f64,
+    pub point: Vec3,
+    pub normal: Vec3, // unit length, pointing out of the surface
+    pub u: f64,       // surface coordinates for texturing
+    pub v: f64,
+    pub occlusion: f64, // 0 out in the open, up to 1 deep in crevices
+}
 
 #[derive(Debug, Copy, Clone)]
 pub enum Object {
@@ -14,10 +28,57 @@ pub enum Object {
         center: Vec3,
         radius: f64,
     },
+    Plane {
+        point: Vec3,
+        normal: Vec3,
+        tangent: Vec3, // unit, where u runs. v runs along normal x tangent.
+        extent: Option<(f64, f64)>, // half sizes along u and v, None for infinite
+    },
+    Box {
+        min: Vec3,
+        max: Vec3,
+        orientation: Quaternion, // rotation about the box's center
+    },
+    Cylinder {
+        a: Vec3,
+        b: Vec3,
+        radius: f64,
+    },
+    Cone {
+        a: Vec3,
+        b: Vec3,
+        radius_a: f64,
+        radius_b: f64,
+    },
+    Capsule {
+        a: Vec3,
+        b: Vec3,
+        radius: f64,
+    },
+    Torus {
+        center: Vec3,
+        major_radius: f64,
+        minor_radius: f64,
+        orientation: Quaternion, // unrotated, the ring lies in the xz plane
+    },
+    Quad {
+        origin: Vec3,
+        edge_u: Vec3,
+        edge_v: Vec3,
+    },
+    Disk {
+        center: Vec3,
+        normal: Vec3,
+        tangent: Vec3, // as for Plane
+        radius: f64,
+    },
 }
 
 pub trait ObjectTrait {
-    fn hit(&self, r: &Ray) -> bool;
+    fn intersect(&self, r: &Ray) -> Option<Hit>;
+    fn hit(&self, r: &Ray) -> bool {
+        self.intersect(r).is_some()
+    }
     fn move_to(&self, v: Vec3) -> Self;
     fn rotate_around_center(&self, euler: Vec3) -> Self;
     fn rotate_around_point(&self, euler: Vec3, point: Vec3) -> Self;
@@ -43,52 +104,652 @@ impl Object {
             radius: *radius,
         }
     }
+    pub fn new_plane(point: &Vec3, normal: &Vec3) -> Self {
+        let normal: Vec3 = normal.normalize();
+        Object::Plane {
+            point: *point,
+            normal,
+            tangent: default_tangent(&normal),
+            extent: None,
+        }
+    }
+    pub fn new_finite_plane(point: &Vec3, normal: &Vec3, half_width: f64, half_depth: f64) -> Self {
+        let normal: Vec3 = normal.normalize();
+        Object::Plane {
+            point: *point,
+            normal,
+            tangent: default_tangent(&normal),
+            extent: Some((half_width, half_depth)),
+        }
+    }
+    pub fn new_box(min: &Vec3, max: &Vec3) -> Self {
+        Object::new_oriented_box(min, max, &Quaternion::new(1.0, 0.0, 0.0, 0.0))
+    }
+    pub fn new_oriented_box(min: &Vec3, max: &Vec3, orientation: &Quaternion) -> Self {
+        Object::Box {
+            min: *min,
+            max: *max,
+            orientation: *orientation,
+        }
+    }
+    pub fn new_cylinder(a: &Vec3, b: &Vec3, radius: f64) -> Self {
+        Object::Cylinder {
+            a: *a,
+            b: *b,
+            radius,
+        }
+    }
+    pub fn new_cone(a: &Vec3, b: &Vec3, radius_a: f64, radius_b: f64) -> Self {
+        Object::Cone {
+            a: *a,
+            b: *b,
+            radius_a,
+            radius_b,
+        }
+    }
+    pub fn new_capsule(a: &Vec3, b: &Vec3, radius: f64) -> Self {
+        Object::Capsule {
+            a: *a,
+            b: *b,
+            radius,
+        }
+    }
+    pub fn new_torus(
+        center: &Vec3,
+        major_radius: f64,
+        minor_radius: f64,
+        orientation: &Quaternion,
+    ) -> Self {
+        Object::Torus {
+            center: *center,
+            major_radius,
+            minor_radius,
+            orientation: *orientation,
+        }
+    }
+    pub fn new_quad(origin: &Vec3, edge_u: &Vec3, edge_v: &Vec3) -> Self {
+        Object::Quad {
+            origin: *origin,
+            edge_u: *edge_u,
+            edge_v: *edge_v,
+        }
+    }
+    pub fn new_disk(center: &Vec3, normal: &Vec3, radius: f64) -> Self {
+        let normal: Vec3 = normal.normalize();
+        Object::Disk {
+            center: *center,
+            normal,
+            tangent: default_tangent(&normal),
+            radius,
+        }
+    }
+
+    // Axis-aligned box around the object, None for infinite planes
+    pub fn bounds(&self) -> Option<Aabb> {
+        match self {
+            Self::Triangle { a, b, c, center: _ } => Some(Aabb::from_points(&[*a, *b, *c])),
+            Self::Sphere { center, radius } => {
+                let extent = Vec3 {
+                    x: *radius,
+                    y: *radius,
+                    z: *radius,
+                };
+                Some(Aabb::new(*center - extent, *center + extent))
+            }
+            Self::Plane {
+                point,
+                normal,
+                tangent,
+                extent,
+            } => {
+                let (half_width, half_depth) = (*extent)?;
+                let (u, v) = (
+                    *tangent * half_width,
+                    Vec3::cross(normal, tangent) * half_depth,
+                );
+                Some(Aabb::from_points(&[
+                    *point - u - v,
+                    *point + u - v,
+                    *point + u + v,
+                    *point - u + v,
+                ]))
+            }
+            Self::Box {
+                min,
+                max,
+                orientation,
+            } => {
+                let center: Vec3 = (*min + *max) / 2.0;
+                let corners: Vec<Vec3> = (0..8)
+                    .map(|i| {
+                        let corner = Vec3 {
+                            x: if i & 1 == 0 { min.x } else { max.x },
+                            y: if i & 2 == 0 { min.y } else { max.y },
+                            z: if i & 4 == 0 { min.z } else { max.z },
+                        };
+                        orientation.rotate(&(corner - center)) + center
+                    })
+                    .collect();
+                Some(Aabb::from_points(&corners))
+            }
+            Self::Cylinder { a, b, radius } => {
+                let axis: Vec3 = *b - *a;
+                let axis: Vec3 = axis.normalize();
+                Some(disk_bounds(a, &axis, *radius).union(&disk_bounds(b, &axis, *radius)))
+            }
+            // Everything lies within the hull of the two end spheres
+            Self::Capsule { a, b, radius } => Some(
+                Object::new_sphere(a, radius)
+                    .bounds()?
+                    .union(&Object::new_sphere(b, radius).bounds()?),
+            ),
+            Self::Cone {
+                a,
+                b,
+                radius_a,
+                radius_b,
+            } => {
+                let axis: Vec3 = *b - *a;
+                let axis: Vec3 = axis.normalize();
+                Some(disk_bounds(a, &axis, *radius_a).union(&disk_bounds(b, &axis, *radius_b)))
+            }
+            Self::Torus {
+                center,
+                major_radius,
+                minor_radius,
+                orientation,
+            } => {
+                // The ring's disk, thickened by the tube in every direction
+                let axis: Vec3 = orientation.rotate(&Vec3 {
+                    x: 0.0,
+                    y: 1.0,
+                    z: 0.0,
+                });
+                let ring: Aabb = disk_bounds(center, &axis, *major_radius);
+                let tube = Vec3 {
+                    x: *minor_radius,
+                    y: *minor_radius,
+                    z: *minor_radius,
+                };
+                Some(Aabb::new(ring.min - tube, ring.max + tube))
+            }
+            Self::Quad {
+                origin,
+                edge_u,
+                edge_v,
+            } => Some(Aabb::from_points(&[
+                *origin,
+                *origin + *edge_u,
+                *origin + *edge_v,
+                *origin + *edge_u + *edge_v,
+            ])),
+            Self::Disk {
+                center,
+                normal,
+                tangent: _,
+                radius,
+            } => Some(disk_bounds(center, normal, *radius)),
+        }
+    }
 }
-impl ObjectTrait for Object {
-    fn hit(&self, r: &Ray) -> bool {
-        match &self {
-            Self::Triangle { a, b, c, center: _ } => {
-                let e1: Vec3 = *b - *a;
-                let e2: Vec3 = *c - *a;
 
-                let ray_cross_e2: Vec3 = Vec3::cross(&r.direction(), &e2);
-                let det: f64 = Vec3::dot(&e1, &ray_cross_e2);
+// Bounds of a circle around `center` facing the unit `normal`. Along each axis
+// it reaches radius * sin of the angle between that axis and the normal.
+fn disk_bounds(center: &Vec3, normal: &Vec3, radius: f64) -> Aabb {
+    let reach = |n: f64| radius * sqrt((1.0 - n * n).max(0.0));
+    let extent = Vec3 {
+        x: reach(normal.x),
+        y: reach(normal.y),
+        z: reach(normal.z),
+    };
+    Aabb::new(*center - extent, *center + extent)
+}
 
-                if det > -f64::EPSILON && det < f64::EPSILON {
-                    return false; // This ray is parallel to this triangle.
-                }
+// Möller–Trumbore ray/triangle intersection. u and v are the barycentric
+// weights of b and c at the hit.
+pub fn triangle_intersect(r: &Ray, a: &Vec3, b: &Vec3, c: &Vec3) -> Option<Hit> {
+    let e1: Vec3 = *b - *a;
+    let e2: Vec3 = *c - *a;
 
-                let inv_det: f64 = 1.0 / det;
-                let s: Vec3 = r.origin() - *a;
-                let u: f64 = inv_det * Vec3::dot(&s, &ray_cross_e2);
-                if u < 0.0 || u > 1.0 {
-                    return false;
-                }
+    let ray_cross_e2: Vec3 = Vec3::cross(&r.direction(), &e2);
+    let det: f64 = Vec3::dot(&e1, &ray_cross_e2);
 
-                let s_cross_e1: Vec3 = Vec3::cross(&s, &e1);
-                let v: f64 = inv_det * Vec3::dot(&r.direction(), &s_cross_e1);
-                if v < 0.0 || u + v > 1.0 {
-                    return false;
-                }
-                // At this stage we can compute t to find out where the intersection point is on the line.
-                let t = inv_det * Vec3::dot(&e2, &s_cross_e1);
-
-                if t > f64::EPSILON {
-                    // ray intersection
-                    let intersection_point = r.origin() + r.direction() * t;
-                    return true;
-                } else {
-                    // This means that there is a line intersection but not a ray intersection.
-                    return false;
-                }
+    if det > -f64::EPSILON && det < f64::EPSILON {
+        return None; // This ray is parallel to this triangle.
+    }
+
+    let inv_det: f64 = 1.0 / det;
+    let s: Vec3 = r.origin() - *a;
+    let u: f64 = inv_det * Vec3::dot(&s, &ray_cross_e2);
+    if u < 0.0 || u > 1.0 {
+        return None;
+    }
+
+    let s_cross_e1: Vec3 = Vec3::cross(&s, &e1);
+    let v: f64 = inv_det * Vec3::dot(&r.direction(), &s_cross_e1);
+    if v < 0.0 || u + v > 1.0 {
+        return None;
+    }
+    // At this stage we can compute t to find out where the intersection point is on the line.
+    let t = inv_det * Vec3::dot(&e2, &s_cross_e1);
+
+    if t > f64::EPSILON {
+        // ray intersection
+        let normal: Vec3 = Vec3::cross(&e1, &e2);
+        Some(Hit {
+            t,
+            point: r.origin() + r.direction() * t,
+            normal: normal.normalize(),
+            u,
+            v,
+            occlusion: 0.0,
+        })
+    } else {
+        // This means that there is a line intersection but not a ray intersection.
+        None
+    }
+}
+
+// Distance along the ray to the plane through `point`, if it lies ahead
+fn plane_intersect(r: &Ray, point: &Vec3, normal: &Vec3) -> Option<f64> {
+    let denom: f64 = Vec3::dot(normal, &r.direction());
+    if denom > -f64::EPSILON && denom < f64::EPSILON {
+        return None; // Parallel to the plane.
+    }
+    let t: f64 = Vec3::dot(&(*point - r.origin()), normal) / denom;
+    if t <= f64::EPSILON {
+        return None;
+    }
+    Some(t)
+}
+
+// Some unit vector in the plane with this normal, for surfaces that weren't
+// told which way their u runs
+fn default_tangent(normal: &Vec3) -> Vec3 {
+    let helper: Vec3 = if fabs(normal.x) < 0.9 {
+        Vec3 {
+            x: 1.0,
+            y: 0.0,
+            z: 0.0,
+        }
+    } else {
+        Vec3 {
+            x: 0.0,
+            y: 1.0,
+            z: 0.0,
+        }
+    };
+    Vec3::cross(&helper, normal).normalize()
+}
+
+// Keeps whichever candidate is nearest in front of the ray
+fn closer(best: Option<(f64, Vec3)>, t: f64, normal: Vec3) -> Option<(f64, Vec3)> {
+    if t > f64::EPSILON && best.is_none_or(|(best_t, _)| t < best_t) {
+        Some((t, normal))
+    } else {
+        best
+    }
+}
+
+// Truncated cone from a (radius_a) to b (radius_b), optionally closed by flat caps.
+// A cylinder is the case radius_a == radius_b.
+fn cone_intersect(
+    r: &Ray,
+    a: &Vec3,
+    b: &Vec3,
+    radius_a: f64,
+    radius_b: f64,
+    caps: bool,
+) -> Option<(f64, Vec3)> {
+    let ba: Vec3 = *b - *a;
+    let height: f64 = ba.length();
+    let axis: Vec3 = ba / height;
+    let slope: f64 = (radius_b - radius_a) / height;
+
+    let oc: Vec3 = r.origin() - *a;
+    let oc_axial: f64 = Vec3::dot(&oc, &axis);
+    let d_axial: f64 = Vec3::dot(&r.direction(), &axis);
+    let oc_perp: Vec3 = oc - axis * oc_axial;
+    let d_perp: Vec3 = r.direction() - axis * d_axial;
+
+    // |perp(t)|^2 = (radius_a + slope * y(t))^2
+    let radius_o: f64 = radius_a + slope * oc_axial;
+    let qa: f64 = Vec3::dot(&d_perp, &d_perp) - slope * slope * d_axial * d_axial;
+    let qb: f64 = 2.0 * (Vec3::dot(&oc_perp, &d_perp) - slope * d_axial * radius_o);
+    let qc: f64 = Vec3::dot(&oc_perp, &oc_perp) - radius_o * radius_o;
+
+    let mut best: Option<(f64, Vec3)> = None;
+    if let Some((t0, t1)) = solve_quadratic(qa, qb, qc) {
+        for t in [t0, t1] {
+            let y: f64 = oc_axial + t * d_axial;
+            if !(0.0..=height).contains(&y) {
+                continue;
             }
+            let perp: Vec3 = oc_perp + d_perp * t;
+            let normal: Vec3 = perp - axis * ((radius_a + slope * y) * slope);
+            best = closer(best, t, normal.normalize());
+        }
+    }
+
+    if caps && fabs(d_axial) > f64::EPSILON {
+        for (y, radius, normal) in [(0.0, radius_a, -axis), (height, radius_b, axis)] {
+            let t: f64 = (y - oc_axial) / d_axial;
+            let offset: Vec3 = oc_perp + d_perp * t;
+            if Vec3::dot(&offset, &offset) <= radius * radius {
+                best = closer(best, t, normal);
+            }
+        }
+    }
+    best
+}
+
+// Torus centered on the origin around the y axis. Returns the distance along the
+// ray and the outward normal.
+fn torus_intersect(
+    origin: Vec3,
+    direction: Vec3,
+    major_radius: f64,
+    minor_radius: f64,
+) -> Option<(f64, Vec3)> {
+    // Solve with a unit direction for better conditioned coefficients
+    let length: f64 = direction.length();
+    let d: Vec3 = direction / length;
+
+    // Skip the quartic when the ray misses the bounding sphere
+    let bound: f64 = major_radius + minor_radius;
+    let b: f64 = Vec3::dot(&origin, &d);
+    let c: f64 = Vec3::dot(&origin, &origin) - bound * bound;
+    if c > 0.0 && (b > 0.0 || b * b - c < 0.0) {
+        return None;
+    }
+
+    // (|p|^2 + R^2 - r^2)^2 = 4 R^2 (x^2 + z^2) with p = origin + t * d
+    let r2: f64 = major_radius * major_radius;
+    let four_r2: f64 = 4.0 * r2;
+    let e: f64 = Vec3::dot(&origin, &origin) - r2 - minor_radius * minor_radius;
+    let coefficients: [f64; 5] = [
+        e * e - four_r2 * (minor_radius * minor_radius - origin.y * origin.y),
+        4.0 * b * e + 2.0 * four_r2 * origin.y * d.y,
+        2.0 * e + 4.0 * b * b + four_r2 * d.y * d.y,
+        4.0 * b,
+        1.0,
+    ];
+
+    let t: f64 = solve_quartic(coefficients)
+        .into_iter()
+        .filter(|t| *t > 1e-6)
+        .fold(f64::INFINITY, f64::min);
+    if t == f64::INFINITY {
+        return None;
+    }
+
+    // Gradient of the implicit surface
+    let p: Vec3 = origin + d * t;
+    let normal: Vec3 = p * (Vec3::dot(&p, &p) - r2 - minor_radius * minor_radius)
+        + Vec3 {
+            x: 0.0,
+            y: 2.0 * r2 * p.y,
+            z: 0.0,
+        };
+    Some((t / length, normal.normalize()))
+}
+
+// Slab test against an axis-aligned box. Returns the distance along the ray and the
+// outward normal of the face it crosses, which is the exit face if the ray starts inside.
+fn slab_intersect(origin: Vec3, direction: Vec3, min: Vec3, max: Vec3) -> Option<(f64, Vec3)> {
+    let origin = [origin.x, origin.y, origin.z];
+    let direction = [direction.x, direction.y, direction.z];
+    let min = [min.x, min.y, min.z];
+    let max = [max.x, max.y, max.z];
+
+    let mut t_near: f64 = f64::NEG_INFINITY;
+    let mut t_far: f64 = f64::INFINITY;
+    let mut near_axis: usize = 0;
+    let mut far_axis: usize = 0;
+    for axis in 0..3 {
+        let inv_dir: f64 = 1.0 / direction[axis];
+        let mut t0: f64 = (min[axis] - origin[axis]) * inv_dir;
+        let mut t1: f64 = (max[axis] - origin[axis]) * inv_dir;
+        if t0 > t1 {
+            std::mem::swap(&mut t0, &mut t1);
+        }
+        if t0 > t_near {
+            t_near = t0;
+            near_axis = axis;
+        }
+        if t1 < t_far {
+            t_far = t1;
+            far_axis = axis;
+        }
+    }
+    if t_near > t_far || t_far <= f64::EPSILON {
+        return None;
+    }
+
+    // The entry face faces against the ray, the exit face along it
+    let (t, axis, sign) = if t_near > f64::EPSILON {
+        (t_near, near_axis, -direction[near_axis].signum())
+    } else {
+        (t_far, far_axis, direction[far_axis].signum())
+    };
+    let mut normal = [0.0; 3];
+    normal[axis] = sign;
+    Some((
+        t,
+        Vec3 {
+            x: normal[0],
+            y: normal[1],
+            z: normal[2],
+        },
+    ))
+}
+impl ObjectTrait for Object {
+    fn intersect(&self, r: &Ray) -> Option<Hit> {
+        match &self {
+            Self::Triangle { a, b, c, center: _ } => triangle_intersect(r, a, b, c),
             Self::Sphere { center, radius } => {
                 let oc: Vec3 = *center - r.origin();
                 let a: f64 = Vec3::dot(&r.direction(), &r.direction());
                 let b: f64 = -2.0 * Vec3::dot(&r.direction(), &oc);
                 let c: f64 = Vec3::dot(&oc, &oc) - radius * radius;
                 let discriminant: f64 = b * b - 4.0 * a * c;
-                return discriminant >= 0.0;
+                if discriminant < 0.0 {
+                    return None;
+                }
+
+                // Nearest root in front of the ray
+                let root: f64 = sqrt(discriminant);
+                let mut t: f64 = (-b - root) / (2.0 * a);
+                if t <= f64::EPSILON {
+                    t = (-b + root) / (2.0 * a);
+                }
+                if t <= f64::EPSILON {
+                    return None;
+                }
+                let point: Vec3 = r.origin() + r.direction() * t;
+                let normal: Vec3 = (point - *center) / *radius;
+                Some(Hit {
+                    t,
+                    point,
+                    normal,
+                    u: 0.5 + libm::atan2(normal.z, normal.x) / (2.0 * PI),
+                    v: libm::acos(normal.y.clamp(-1.0, 1.0)) / PI,
+                    occlusion: 0.0,
+                })
+            }
+            Self::Plane {
+                point,
+                normal,
+                tangent,
+                extent,
+            } => {
+                let t: f64 = plane_intersect(r, point, normal)?;
+                let hit_point: Vec3 = r.origin() + r.direction() * t;
+
+                let offset: Vec3 = hit_point - *point;
+                let u: f64 = Vec3::dot(&offset, tangent);
+                let v: f64 = Vec3::dot(&offset, &Vec3::cross(normal, tangent));
+                if let Some((half_width, half_depth)) = extent {
+                    if fabs(u) > *half_width || fabs(v) > *half_depth {
+                        return None;
+                    }
+                }
+                Some(Hit {
+                    t,
+                    point: hit_point,
+                    normal: *normal,
+                    u,
+                    v,
+                    occlusion: 0.0,
+                })
+            }
+            Self::Box {
+                min,
+                max,
+                orientation,
+            } => {
+                // Intersect in the box's own frame, where it is axis-aligned
+                let center: Vec3 = (*min + *max) / 2.0;
+                let inverse: Quaternion = orientation.conjugate();
+                let origin: Vec3 = inverse.rotate(&(r.origin() - center)) + center;
+                let direction: Vec3 = inverse.rotate(&r.direction());
+
+                let (t, normal) = slab_intersect(origin, direction, *min, *max)?;
+                Some(Hit {
+                    t,
+                    point: r.origin() + r.direction() * t,
+                    normal: orientation.rotate(&normal),
+                    u: 0.0,
+                    v: 0.0,
+                    occlusion: 0.0,
+                })
+            }
+            Self::Cylinder { a, b, radius } => {
+                let (t, normal) = cone_intersect(r, a, b, *radius, *radius, true)?;
+                Some(Hit {
+                    t,
+                    point: r.origin() + r.direction() * t,
+                    normal,
+                    u: 0.0,
+                    v: 0.0,
+                    occlusion: 0.0,
+                })
+            }
+            Self::Cone {
+                a,
+                b,
+                radius_a,
+                radius_b,
+            } => {
+                let (t, normal) = cone_intersect(r, a, b, *radius_a, *radius_b, true)?;
+                Some(Hit {
+                    t,
+                    point: r.origin() + r.direction() * t,
+                    normal,
+                    u: 0.0,
+                    v: 0.0,
+                    occlusion: 0.0,
+                })
+            }
+            Self::Capsule { a, b, radius } => {
+                // An open cylinder plus the outer half of a sphere at each end
+                let mut best: Option<(f64, Vec3)> =
+                    cone_intersect(r, a, b, *radius, *radius, false);
+                let ba: Vec3 = *b - *a;
+                for (end, outward) in [(a, -ba), (b, ba)] {
+                    let oc: Vec3 = r.origin() - *end;
+                    let roots = solve_quadratic(
+                        Vec3::dot(&r.direction(), &r.direction()),
+                        2.0 * Vec3::dot(&oc, &r.direction()),
+                        Vec3::dot(&oc, &oc) - radius * radius,
+                    );
+                    if let Some((t0, t1)) = roots {
+                        for t in [t0, t1] {
+                            let offset: Vec3 = oc + r.direction() * t;
+                            if Vec3::dot(&offset, &outward) >= 0.0 {
+                                best = closer(best, t, offset / *radius);
+                            }
+                        }
+                    }
+                }
+                let (t, normal) = best?;
+                Some(Hit {
+                    t,
+                    point: r.origin() + r.direction() * t,
+                    normal,
+                    u: 0.0,
+                    v: 0.0,
+                    occlusion: 0.0,
+                })
+            }
+            Self::Torus {
+                center,
+                major_radius,
+                minor_radius,
+                orientation,
+            } => {
+                let inverse: Quaternion = orientation.conjugate();
+                let origin: Vec3 = inverse.rotate(&(r.origin() - *center));
+                let direction: Vec3 = inverse.rotate(&r.direction());
+
+                let (t, normal) = torus_intersect(origin, direction, *major_radius, *minor_radius)?;
+                Some(Hit {
+                    t,
+                    point: r.origin() + r.direction() * t,
+                    normal: orientation.rotate(&normal),
+                    u: 0.0,
+                    v: 0.0,
+                    occlusion: 0.0,
+                })
+            }
+            Self::Quad {
+                origin,
+                edge_u,
+                edge_v,
+            } => {
+                let n: Vec3 = Vec3::cross(edge_u, edge_v);
+                let t: f64 = plane_intersect(r, origin, &n)?;
+                let point: Vec3 = r.origin() + r.direction() * t;
+
+                // Coordinates of the hit in the basis of the two edges
+                let w: Vec3 = n / Vec3::dot(&n, &n);
+                let offset: Vec3 = point - *origin;
+                let u: f64 = Vec3::dot(&w, &Vec3::cross(&offset, edge_v));
+                let v: f64 = Vec3::dot(&w, &Vec3::cross(edge_u, &offset));
+                if !(0.0..=1.0).contains(&u) || !(0.0..=1.0).contains(&v) {
+                    return None;
+                }
+                Some(Hit {
+                    t,
+                    point,
+                    normal: n.normalize(),
+                    u,
+                    v,
+                    occlusion: 0.0,
+                })
+            }
+            Self::Disk {
+                center,
+                normal,
+                tangent,
+                radius,
+            } => {
+                let t: f64 = plane_intersect(r, center, normal)?;
+                let point: Vec3 = r.origin() + r.direction() * t;
+                let offset: Vec3 = point - *center;
+                if Vec3::dot(&offset, &offset) > radius * radius {
+                    return None;
+                }
+
+                // The disk's bounding square mapped onto [0, 1]
+                Some(Hit {
+                    t,
+                    point,
+                    normal: *normal,
+                    u: 0.5 + Vec3::dot(&offset, tangent) / (2.0 * radius),
+                    v: 0.5 + Vec3::dot(&offset, &Vec3::cross(normal, tangent)) / (2.0 * radius),
+                    occlusion: 0.0,
+                })
             }
         }
     }
@@ -98,6 +759,52 @@ impl ObjectTrait for Object {
                 Self::new_triangle(&(*a + v), &(*b + v), &(*c + v))
             }
             Self::Sphere { center, radius } => Self::new_sphere(&(*center + v), radius),
+            Self::Plane {
+                point,
+                normal,
+                tangent,
+                extent,
+            } => Object::Plane {
+                point: *point + v,
+                normal: *normal,
+                tangent: *tangent,
+                extent: *extent,
+            },
+            Self::Box {
+                min,
+                max,
+                orientation,
+            } => Self::new_oriented_box(&(*min + v), &(*max + v), orientation),
+            Self::Cylinder { a, b, radius } => Self::new_cylinder(&(*a + v), &(*b + v), *radius),
+            Self::Cone {
+                a,
+                b,
+                radius_a,
+                radius_b,
+            } => Self::new_cone(&(*a + v), &(*b + v), *radius_a, *radius_b),
+            Self::Capsule { a, b, radius } => Self::new_capsule(&(*a + v), &(*b + v), *radius),
+            Self::Torus {
+                center,
+                major_radius,
+                minor_radius,
+                orientation,
+            } => Self::new_torus(&(*center + v), *major_radius, *minor_radius, orientation),
+            Self::Quad {
+                origin,
+                edge_u,
+                edge_v,
+            } => Self::new_quad(&(*origin + v), edge_u, edge_v),
+            Self::Disk {
+                center,
+                normal,
+                tangent,
+                radius,
+            } => Object::Disk {
+                center: *center + v,
+                normal: *normal,
+                tangent: *tangent,
+                radius: *radius,
+            },
         }
     }
     fn rotate_around_center(&self, euler: Vec3) -> Self {
@@ -113,6 +820,95 @@ impl ObjectTrait for Object {
                 center: _,
                 radius: _,
             } => *self,
+            Self::Plane {
+                point,
+                normal,
+                tangent,
+                extent,
+            } => {
+                let quaternion = Quaternion::euler_to_quaternion(euler);
+                Object::Plane {
+                    point: *point,
+                    normal: quaternion.rotate(normal),
+                    tangent: quaternion.rotate(tangent),
+                    extent: *extent,
+                }
+            }
+            Self::Box {
+                min,
+                max,
+                orientation,
+            } => {
+                let quaternion = Quaternion::euler_to_quaternion(euler);
+                Self::new_oriented_box(min, max, &(quaternion * *orientation).normalize())
+            }
+            Self::Cylinder { a, b, radius } => {
+                let quaternion = Quaternion::euler_to_quaternion(euler);
+                let center: Vec3 = (*a + *b) / 2.0;
+                let a: Vec3 = a.rotate_around_point(&center, &quaternion);
+                let b: Vec3 = b.rotate_around_point(&center, &quaternion);
+                Self::new_cylinder(&a, &b, *radius)
+            }
+            Self::Cone {
+                a,
+                b,
+                radius_a,
+                radius_b,
+            } => {
+                let quaternion = Quaternion::euler_to_quaternion(euler);
+                let center: Vec3 = (*a + *b) / 2.0;
+                let a: Vec3 = a.rotate_around_point(&center, &quaternion);
+                let b: Vec3 = b.rotate_around_point(&center, &quaternion);
+                Self::new_cone(&a, &b, *radius_a, *radius_b)
+            }
+            Self::Capsule { a, b, radius } => {
+                let quaternion = Quaternion::euler_to_quaternion(euler);
+                let center: Vec3 = (*a + *b) / 2.0;
+                let a: Vec3 = a.rotate_around_point(&center, &quaternion);
+                let b: Vec3 = b.rotate_around_point(&center, &quaternion);
+                Self::new_capsule(&a, &b, *radius)
+            }
+            Self::Torus {
+                center,
+                major_radius,
+                minor_radius,
+                orientation,
+            } => {
+                let quaternion = Quaternion::euler_to_quaternion(euler);
+                Self::new_torus(
+                    center,
+                    *major_radius,
+                    *minor_radius,
+                    &(quaternion * *orientation).normalize(),
+                )
+            }
+            Self::Quad {
+                origin,
+                edge_u,
+                edge_v,
+            } => {
+                let quaternion = Quaternion::euler_to_quaternion(euler);
+                let center: Vec3 = *origin + (*edge_u + *edge_v) / 2.0;
+                Self::new_quad(
+                    &origin.rotate_around_point(&center, &quaternion),
+                    &quaternion.rotate(edge_u),
+                    &quaternion.rotate(edge_v),
+                )
+            }
+            Self::Disk {
+                center,
+                normal,
+                tangent,
+                radius,
+            } => {
+                let quaternion = Quaternion::euler_to_quaternion(euler);
+                Object::Disk {
+                    center: *center,
+                    normal: quaternion.rotate(normal),
+                    tangent: quaternion.rotate(tangent),
+                    radius: *radius,
+                }
+            }
         }
     }
     fn rotate_around_point(&self, euler: Vec3, point: Vec3) -> Self {
@@ -129,6 +925,295 @@ impl ObjectTrait for Object {
                 let center: Vec3 = center.rotate_around_point(&point, &quaternion);
                 Object::new_sphere(&center, radius)
             }
+            Self::Plane {
+                point: plane_point,
+                normal,
+                tangent,
+                extent,
+            } => {
+                let quaternion = Quaternion::euler_to_quaternion(euler);
+                Object::Plane {
+                    point: plane_point.rotate_around_point(&point, &quaternion),
+                    normal: quaternion.rotate(normal),
+                    tangent: quaternion.rotate(tangent),
+                    extent: *extent,
+                }
+            }
+            Self::Box {
+                min,
+                max,
+                orientation,
+            } => {
+                let quaternion = Quaternion::euler_to_quaternion(euler);
+                let center: Vec3 = (*min + *max) / 2.0;
+                let offset: Vec3 = center.rotate_around_point(&point, &quaternion) - center;
+                Self::new_oriented_box(
+                    &(*min + offset),
+                    &(*max + offset),
+                    &(quaternion * *orientation).normalize(),
+                )
+            }
+            Self::Cylinder { a, b, radius } => {
+                let quaternion = Quaternion::euler_to_quaternion(euler);
+                let a: Vec3 = a.rotate_around_point(&point, &quaternion);
+                let b: Vec3 = b.rotate_around_point(&point, &quaternion);
+                Self::new_cylinder(&a, &b, *radius)
+            }
+            Self::Cone {
+                a,
+                b,
+                radius_a,
+                radius_b,
+            } => {
+                let quaternion = Quaternion::euler_to_quaternion(euler);
+                let a: Vec3 = a.rotate_around_point(&point, &quaternion);
+                let b: Vec3 = b.rotate_around_point(&point, &quaternion);
+                Self::new_cone(&a, &b, *radius_a, *radius_b)
+            }
+            Self::Capsule { a, b, radius } => {
+                let quaternion = Quaternion::euler_to_quaternion(euler);
+                let a: Vec3 = a.rotate_around_point(&point, &quaternion);
+                let b: Vec3 = b.rotate_around_point(&point, &quaternion);
+                Self::new_capsule(&a, &b, *radius)
+            }
+            Self::Torus {
+                center,
+                major_radius,
+                minor_radius,
+                orientation,
+            } => {
+                let quaternion = Quaternion::euler_to_quaternion(euler);
+                Self::new_torus(
+                    &center.rotate_around_point(&point, &quaternion),
+                    *major_radius,
+                    *minor_radius,
+                    &(quaternion * *orientation).normalize(),
+                )
+            }
+            Self::Quad {
+                origin,
+                edge_u,
+                edge_v,
+            } => {
+                let quaternion = Quaternion::euler_to_quaternion(euler);
+                Self::new_quad(
+                    &origin.rotate_around_point(&point, &quaternion),
+                    &quaternion.rotate(edge_u),
+                    &quaternion.rotate(edge_v),
+                )
+            }
+            Self::Disk {
+                center,
+                normal,
+                tangent,
+                radius,
+            } => {
+                let quaternion = Quaternion::euler_to_quaternion(euler);
+                Object::Disk {
+                    center: center.rotate_around_point(&point, &quaternion),
+                    normal: quaternion.rotate(normal),
+                    tangent: quaternion.rotate(tangent),
+                    radius: *radius,
+                }
+            }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64::consts::FRAC_PI_2;
+
+    fn down_at(x: f64, z: f64) -> Ray {
+        Ray::new(
+            Vec3 { x, y: 1.0, z },
+            Vec3 {
+                x: 0.0,
+                y: -1.0,
+                z: 0.0,
+            },
+        )
+    }
+
+    #[test]
+    fn finite_plane_extents_turn_with_the_plane() {
+        let floor: Object = Object::new_finite_plane(
+            &Vec3 {
+                x: 0.0,
+                y: 0.0,
+                z: 0.0,
+            },
+            &Vec3 {
+                x: 0.0,
+                y: 1.0,
+                z: 0.0,
+            },
+            2.0,
+            0.5,
+        );
+        let Object::Plane { tangent, .. } = floor else {
+            unreachable!()
+        };
+        let far: Vec3 = tangent * 1.5; // inside along the long side only
+        assert!(floor.hit(&down_at(far.x, far.z)));
+
+        // A quarter turn about its own normal swaps which side is long
+        let quarter = Vec3 {
+            x: 0.0,
+            y: FRAC_PI_2,
+            z: 0.0,
+        };
+        let turned: Object = floor.rotate_around_center(quarter);
+        assert!(!turned.hit(&down_at(far.x, far.z)));
+        let far: Vec3 = Quaternion::euler_to_quaternion(quarter).rotate(&far);
+        let hit: Hit = turned.intersect(&down_at(far.x, far.z)).unwrap();
+        assert!(fabs(hit.u - 1.5) < 1e-12 && fabs(hit.v) < 1e-12);
+    }
+
+    #[test]
+    fn disk_uv_turn_with_the_disk() {
+        let disk: Object = Object::new_disk(
+            &Vec3 {
+                x: 0.0,
+                y: 0.0,
+                z: 0.0,
+            },
+            &Vec3 {
+                x: 0.0,
+                y: 1.0,
+                z: 0.0,
+            },
+            1.0,
+        );
+        let Object::Disk { tangent, .. } = disk else {
+            unreachable!()
+        };
+        let edge: Vec3 = tangent * 0.5;
+        let hit: Hit = disk.intersect(&down_at(edge.x, edge.z)).unwrap();
+        assert!(fabs(hit.u - 0.75) < 1e-12 && fabs(hit.v - 0.5) < 1e-12);
+
+        // The same spot on the disk keeps its u, v however the disk is turned
+        let mut turned: Object = disk;
+        let mut edge: Vec3 = edge;
+        let step = Vec3 {
+            x: 0.0,
+            y: 0.3,
+            z: 0.0,
+        };
+        for _ in 0..10 {
+            turned = turned.rotate_around_center(step);
+            edge = Quaternion::euler_to_quaternion(step).rotate(&edge);
+            let hit: Hit = turned.intersect(&down_at(edge.x, edge.z)).unwrap();
+            assert!(fabs(hit.u - 0.75) < 1e-9 && fabs(hit.v - 0.5) < 1e-9);
+        }
+    }
+
+    // Distance from the torus surface: positive outside, negative inside the tube
+    fn torus_distance(p: &Vec3, major_radius: f64, minor_radius: f64) -> f64 {
+        let ring: f64 = sqrt(p.x * p.x + p.z * p.z) - major_radius;
+        sqrt(ring * ring + p.y * p.y) - minor_radius
+    }
+
+    #[test]
+    fn torus_hits_lie_on_the_implicit_surface() {
+        let (major, minor) = (1.0, 0.25);
+        let mut hits: usize = 0;
+        for i in 0..500 {
+            let f = i as f64;
+            let origin = Vec3 {
+                x: 3.0 * libm::sin(f * 0.7),
+                y: 2.0 * libm::sin(f * 1.3),
+                z: 3.0 * libm::cos(f * 0.7),
+            };
+            // Aim near the ring with some spread so plenty of rays miss too
+            let target = Vec3 {
+                x: libm::cos(f * 2.1) * (0.6 + 0.6 * libm::sin(f * 0.9)),
+                y: 0.3 * libm::sin(f * 3.7),
+                z: libm::sin(f * 2.1) * (0.6 + 0.6 * libm::sin(f * 0.9)),
+            };
+            let direction: Vec3 = (target - origin) * 0.5; // not unit length
+            let Some((t, normal)) = torus_intersect(origin, direction, major, minor) else {
+                continue;
+            };
+            hits += 1;
+            let p: Vec3 = origin + direction * t;
+            assert!(fabs(torus_distance(&p, major, minor)) < 1e-9, "{:?}", p);
+
+            // Nothing closer along the ray is on the surface or inside the tube
+            for step in 1..100 {
+                let q: Vec3 = origin + direction * (t * step as f64 / 100.0);
+                assert!(torus_distance(&q, major, minor) > -1e-9);
+            }
+
+            // The normal points from the tube's core out through the hit
+            let ring: Vec3 = Vec3 {
+                x: p.x,
+                y: 0.0,
+                z: p.z,
+            }
+            .normalize()
+                * major;
+            assert!(normal.approx_eq(&(p - ring).normalize(), 1e-6));
+        }
+        assert!(hits > 100);
+    }
+
+    #[test]
+    fn torus_grazing_rays() {
+        let (major, minor) = (1.0, 0.25);
+        let along_x = Vec3 {
+            x: 1.0,
+            y: 0.0,
+            z: 0.0,
+        };
+        // Just under the top of the tube, hitting it on the far side of x = -1
+        let (t, _) = torus_intersect(
+            Vec3 {
+                x: -3.0,
+                y: minor - 1e-6,
+                z: 0.0,
+            },
+            along_x,
+            major,
+            minor,
+        )
+        .unwrap();
+        assert!(fabs(t - 2.0) < 1e-2);
+        // Just over it, missing
+        assert!(torus_intersect(
+            Vec3 {
+                x: -3.0,
+                y: minor + 1e-6,
+                z: 0.0,
+            },
+            along_x,
+            major,
+            minor,
+        )
+        .is_none());
+
+        // Just inside the outer equator, crossing it near z = 0
+        let along_z = Vec3 {
+            x: 0.0,
+            y: 0.0,
+            z: 1.0,
+        };
+        let origin = Vec3 {
+            x: major + minor - 1e-7,
+            y: 0.0,
+            z: -3.0,
+        };
+        let (t, normal) = torus_intersect(origin, along_z, major, minor).unwrap();
+        let p: Vec3 = origin + along_z * t;
+        assert!(fabs(p.z) < 1e-3 && fabs(torus_distance(&p, major, minor)) < 1e-9);
+        assert!(normal.approx_eq(
+            &Vec3 {
+                x: 1.0,
+                y: 0.0,
+                z: 0.0
+            },
+            1e-3
+        ));
+    }
+}
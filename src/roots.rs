@@ -0,0 +1,242 @@
+use libm::{acos, cbrt, cos, fabs, sqrt};
+use std::f64::consts::PI;
+
+// Coefficients below this are treated as zero
+const EPSILON: f64 = 1e-9;
+
+// Both roots of a*t^2 + b*t + c, smallest first
+pub fn solve_quadratic(a: f64, b: f64, c: f64) -> Option<(f64, f64)> {
+    if fabs(a) < f64::EPSILON {
+        if fabs(b) < f64::EPSILON {
+            return None;
+        }
+        let t = -c / b;
+        return Some((t, t));
+    }
+    let discriminant: f64 = b * b - 4.0 * a * c;
+    if discriminant < 0.0 {
+        return None;
+    }
+    let root: f64 = sqrt(discriminant);
+    let t0: f64 = (-b - root) / (2.0 * a);
+    let t1: f64 = (-b + root) / (2.0 * a);
+    Some((t0.min(t1), t0.max(t1)))
+}
+
+// Real roots of c[3]*x^3 + c[2]*x^2 + c[1]*x + c[0] by Cardano's method
+pub fn solve_cubic(c: [f64; 4]) -> Vec<f64> {
+    if fabs(c[3]) < EPSILON {
+        return match solve_quadratic(c[2], c[1], c[0]) {
+            Some((x0, x1)) => vec![x0, x1],
+            None => Vec::new(),
+        };
+    }
+    // Normal form x^3 + a*x^2 + b*x + c = 0, then substitute x = y - a/3 to
+    // eliminate the quadratic term: y^3 + 3p*y + 2q = 0
+    let a: f64 = c[2] / c[3];
+    let b: f64 = c[1] / c[3];
+    let c0: f64 = c[0] / c[3];
+
+    let sq_a: f64 = a * a;
+    let p: f64 = (-sq_a / 3.0 + b) / 3.0;
+    let q: f64 = (2.0 / 27.0 * a * sq_a - a * b / 3.0 + c0) / 2.0;
+    let cb_p: f64 = p * p * p;
+    let d: f64 = q * q + cb_p;
+
+    // d scales with the sixth power of the roots, so it's only near zero
+    // compared to the terms it's made of
+    let mut roots: Vec<f64> = if fabs(d) <= EPSILON * (q * q + fabs(cb_p)) {
+        // A double root, or a triple one when q is zero
+        let u: f64 = cbrt(-q);
+        vec![2.0 * u, -u]
+    } else if d < 0.0 {
+        // Three real roots
+        let phi: f64 = acos((-q / sqrt(-cb_p)).clamp(-1.0, 1.0)) / 3.0;
+        let t: f64 = 2.0 * sqrt(-p);
+        vec![
+            t * cos(phi),
+            -t * cos(phi + PI / 3.0),
+            -t * cos(phi - PI / 3.0),
+        ]
+    } else {
+        let sqrt_d: f64 = sqrt(d);
+        vec![cbrt(sqrt_d - q) - cbrt(sqrt_d + q)]
+    };
+    for root in roots.iter_mut() {
+        *root -= a / 3.0;
+    }
+    roots
+}
+
+// Real roots of c[4]*x^4 + c[3]*x^3 + c[2]*x^2 + c[1]*x + c[0] by Ferrari's method,
+// each polished with Newton steps against the original polynomial
+pub fn solve_quartic(c: [f64; 5]) -> Vec<f64> {
+    if fabs(c[4]) < EPSILON {
+        return solve_cubic([c[0], c[1], c[2], c[3]]);
+    }
+    // Normal form x^4 + a*x^3 + b*x^2 + c*x + d = 0, then substitute x = y - a/4:
+    // y^4 + p*y^2 + q*y + r = 0
+    let a: f64 = c[3] / c[4];
+    let b: f64 = c[2] / c[4];
+    let c1: f64 = c[1] / c[4];
+    let d: f64 = c[0] / c[4];
+
+    let sq_a: f64 = a * a;
+    let p: f64 = -3.0 / 8.0 * sq_a + b;
+    let q: f64 = sq_a * a / 8.0 - a * b / 2.0 + c1;
+    let r: f64 = -3.0 / 256.0 * sq_a * sq_a + sq_a * b / 16.0 - a * c1 / 4.0 + d;
+
+    let mut roots: Vec<f64> = Vec::new();
+    if fabs(r) < EPSILON {
+        // y * (y^3 + p*y + q) = 0
+        roots = solve_cubic([q, p, 0.0, 1.0]);
+        roots.push(0.0);
+    } else {
+        // A real root of the resolvent cubic splits the quartic into two
+        // quadratics. The largest always leaves both square roots real.
+        let z: f64 = solve_cubic([r * p / 2.0 - q * q / 8.0, -r, -p / 2.0, 1.0])
+            .into_iter()
+            .fold(f64::NEG_INFINITY, f64::max);
+
+        let u: f64 = z * z - r;
+        let v: f64 = 2.0 * z - p;
+        if u < -EPSILON || v < -EPSILON {
+            return roots;
+        }
+        let u: f64 = sqrt(u.max(0.0));
+        let v: f64 = if q < 0.0 {
+            -sqrt(v.max(0.0))
+        } else {
+            sqrt(v.max(0.0))
+        };
+
+        for (b, c) in [(v, z - u), (-v, z + u)] {
+            if let Some((y0, y1)) = solve_quadratic(1.0, b, c) {
+                roots.push(y0);
+                roots.push(y1);
+            }
+        }
+    }
+
+    for root in roots.iter_mut() {
+        *root -= a / 4.0;
+        for _ in 0..2 {
+            let f: f64 = (((c[4] * *root + c[3]) * *root + c[2]) * *root + c[1]) * *root + c[0];
+            let df: f64 = ((4.0 * c[4] * *root + 3.0 * c[3]) * *root + 2.0 * c[2]) * *root + c[1];
+            if fabs(df) > EPSILON {
+                *root -= f / df;
+            }
+        }
+    }
+    roots
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Coefficients, lowest power first, of the monic polynomial with these roots
+    fn polynomial(roots: &[f64]) -> Vec<f64> {
+        let mut c: Vec<f64> = vec![1.0];
+        for root in roots {
+            let mut next: Vec<f64> = vec![0.0; c.len() + 1];
+            for (i, coefficient) in c.iter().enumerate() {
+                next[i + 1] += coefficient;
+                next[i] -= coefficient * root;
+            }
+            c = next;
+        }
+        c
+    }
+
+    // Every expected root is found, and nothing else is
+    fn assert_roots(found: &[f64], expected: &[f64], tolerance: f64) {
+        let near = |x: f64, ys: &[f64]| ys.iter().any(|y| fabs(x - y) < tolerance);
+        assert!(
+            expected.iter().all(|x| near(*x, found)) && found.iter().all(|x| near(*x, expected)),
+            "found {:?}, expected {:?}",
+            found,
+            expected
+        );
+    }
+
+    #[test]
+    fn quadratic_roots_come_smallest_first() {
+        assert_eq!(solve_quadratic(1.0, -1.0, -6.0), Some((-2.0, 3.0)));
+        assert_eq!(solve_quadratic(-1.0, 1.0, 6.0), Some((-2.0, 3.0)));
+        assert_eq!(solve_quadratic(1.0, 0.0, 1.0), None);
+        assert_eq!(solve_quadratic(0.0, 2.0, -4.0), Some((2.0, 2.0)));
+    }
+
+    #[test]
+    fn cubic_with_three_real_roots() {
+        let c: Vec<f64> = polynomial(&[-2.0, 0.5, 3.0]);
+        assert_roots(
+            &solve_cubic([c[0], c[1], c[2], c[3]]),
+            &[-2.0, 0.5, 3.0],
+            1e-9,
+        );
+    }
+
+    #[test]
+    fn cubic_with_one_real_root() {
+        // (x - 2)(x^2 + 1)
+        assert_roots(&solve_cubic([-2.0, 1.0, -2.0, 1.0]), &[2.0], 1e-9);
+    }
+
+    #[test]
+    fn cubic_with_repeated_roots() {
+        let c: Vec<f64> = polynomial(&[1.0, 1.0, -2.0]);
+        assert_roots(&solve_cubic([c[0], c[1], c[2], c[3]]), &[1.0, -2.0], 1e-6);
+        let c: Vec<f64> = polynomial(&[1.5, 1.5, 1.5]);
+        assert_roots(&solve_cubic([c[0], c[1], c[2], c[3]]), &[1.5], 1e-6);
+    }
+
+    #[test]
+    fn quartic_with_four_real_roots() {
+        for roots in [
+            [-3.0, -1.0, 2.0, 5.0],
+            [0.1, 0.2, 0.3, 0.4],
+            [-10.0, 0.0, 1.0, 7.5],
+        ] {
+            let c: Vec<f64> = polynomial(&roots);
+            let found: Vec<f64> = solve_quartic([c[0], c[1], c[2], c[3], c[4]]);
+            assert_eq!(found.len(), 4);
+            assert_roots(&found, &roots, 1e-9);
+        }
+    }
+
+    #[test]
+    fn quartic_with_two_or_no_real_roots() {
+        // (x - 1)(x + 2)(x^2 + 1)
+        assert_roots(
+            &solve_quartic([-2.0, 1.0, -1.0, 1.0, 1.0]),
+            &[1.0, -2.0],
+            1e-9,
+        );
+        // (x^2 + 1)(x^2 + 4)
+        assert!(solve_quartic([4.0, 0.0, 5.0, 0.0, 1.0]).is_empty());
+    }
+
+    #[test]
+    fn quartic_with_repeated_roots() {
+        for roots in [
+            [1.0, 1.0, 3.0, 3.0],
+            [-2.0, -2.0, 0.5, 4.0],
+            [2.0, 2.0, 2.0, -1.0],
+        ] {
+            let c: Vec<f64> = polynomial(&roots);
+            assert_roots(&solve_quartic([c[0], c[1], c[2], c[3], c[4]]), &roots, 1e-4);
+        }
+    }
+
+    #[test]
+    fn quartic_in_the_cubic_fallback() {
+        let c: Vec<f64> = polynomial(&[-1.0, 2.0, 4.0]);
+        assert_roots(
+            &solve_quartic([c[0], c[1], c[2], c[3], 0.0]),
+            &[-1.0, 2.0, 4.0],
+            1e-9,
+        );
+    }
+}
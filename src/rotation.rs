@@ -1,6 +1,51 @@
+use crate::matrix::Mat3;
 use crate::vector::Vec3;
-use libm::{cos, sin, sqrt};
+use libm::{acos, asin, atan2, cos, fabs, sin, sqrt};
+use std::f64::consts::FRAC_PI_2;
 use std::ops;
+
+const PARALLEL: f64 = 0.9995; // dot past which slerp falls back to nlerp
+
+// Order the three axes are turned about. Intrinsic turns follow the body as it
+// turns, extrinsic ones stay with the world axes, so Extrinsic(XYZ) is the same
+// as Intrinsic(ZYX). Blender's XYZ mode is extrinsic.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum EulerOrder {
+    Intrinsic(Axes),
+    Extrinsic(Axes),
+}
+
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Axes {
+    XYZ,
+    XZY,
+    YXZ,
+    YZX,
+    ZXY,
+    ZYX,
+}
+
+impl EulerOrder {
+    // Axis indices, first turn first, as intrinsic turns
+    fn intrinsic_axes(&self) -> [usize; 3] {
+        let axes: [usize; 3] = match self {
+            EulerOrder::Intrinsic(axes) | EulerOrder::Extrinsic(axes) => match axes {
+                Axes::XYZ => [0, 1, 2],
+                Axes::XZY => [0, 2, 1],
+                Axes::YXZ => [1, 0, 2],
+                Axes::YZX => [1, 2, 0],
+                Axes::ZXY => [2, 0, 1],
+                Axes::ZYX => [2, 1, 0],
+            },
+        };
+        match self {
+            EulerOrder::Intrinsic(_) => axes,
+            EulerOrder::Extrinsic(_) => [axes[2], axes[1], axes[0]],
+        }
+    }
+}
+
 #[derive(Debug, Copy, Clone)]
 pub struct Quaternion {
     w: f64,
@@ -14,7 +59,6 @@ impl Quaternion {
         Quaternion { w, x, y, z }
     }
     pub fn new_from_point(point: Vec3) -> Quaternion {
-        let d = sqrt(point.x * point.x + point.y * point.y + point.z * point.z);
         Quaternion {
             w: 0.0,
             x: point.x,
@@ -22,6 +66,54 @@ impl Quaternion {
             z: point.z,
         }
     }
+    // Turns by `angle` radians about `axis`, which need not be unit length.
+    // A zero axis turns nothing.
+    pub fn new_from_axis_angle(axis: Vec3, angle: f64) -> Quaternion {
+        let length = axis.length();
+        if length == 0.0 {
+            return Quaternion::new(1.0, 0.0, 0.0, 0.0);
+        }
+        let s = sin(angle / 2.0) / length;
+        Quaternion {
+            w: cos(angle / 2.0),
+            x: axis.x * s,
+            y: axis.y * s,
+            z: axis.z * s,
+        }
+    }
+    // The shortest arc turning the direction of `from` onto that of `to`. A
+    // zero vector has no direction, so that turns nothing.
+    pub fn new_from_to(from: Vec3, to: Vec3) -> Quaternion {
+        let lengths = sqrt(Vec3::dot(&from, &from) * Vec3::dot(&to, &to));
+        if lengths == 0.0 {
+            return Quaternion::new(1.0, 0.0, 0.0, 0.0);
+        }
+        let axis: Vec3 = Vec3::cross(&from, &to);
+        let w = Vec3::dot(&from, &to) + lengths;
+        if w > 1e-12 * lengths {
+            return Quaternion::new(w, axis.x, axis.y, axis.z).normalize();
+        }
+        // Opposite directions, any axis at right angles does
+        let other: Vec3 = if fabs(from.x) < fabs(from.z) {
+            Vec3 {
+                x: 1.0,
+                y: 0.0,
+                z: 0.0,
+            }
+        } else {
+            Vec3 {
+                x: 0.0,
+                y: 0.0,
+                z: 1.0,
+            }
+        };
+        let axis: Vec3 = Vec3::cross(&from, &other);
+        Quaternion::new(0.0, axis.x, axis.y, axis.z).normalize()
+    }
+
+    pub fn dot(a: &Quaternion, b: &Quaternion) -> f64 {
+        a.w * b.w + a.x * b.x + a.y * b.y + a.z * b.z
+    }
     pub fn conjugate(&self) -> Quaternion {
         Quaternion {
             w: self.w,
@@ -30,6 +122,25 @@ impl Quaternion {
             z: -self.z,
         }
     }
+    // Same as the conjugate for unit quaternions
+    pub fn inverse(&self) -> Quaternion {
+        let length_squared = Quaternion::dot(self, self);
+        Quaternion {
+            w: self.w / length_squared,
+            x: -self.x / length_squared,
+            y: -self.y / length_squared,
+            z: -self.z / length_squared,
+        }
+    }
+    pub fn normalize(&self) -> Quaternion {
+        let length = sqrt(Quaternion::dot(self, self));
+        Quaternion {
+            w: self.w / length,
+            x: self.x / length,
+            y: self.y / length,
+            z: self.z / length,
+        }
+    }
     pub fn rotate(&self, point: &Vec3) -> Vec3 {
         let point_quat: Quaternion = Quaternion::new_from_point(*point);
         let result: Quaternion = *self * point_quat * self.conjugate();
@@ -39,10 +150,10 @@ impl Quaternion {
             z: result.z,
         }
     }
+    // Pitch about x, heading about y and bank about z, all turning clockwise
+    // seen from the positive end of the axis. Same as new_from_euler with the
+    // angles negated and EulerOrder::Intrinsic(Axes::ZXY).
     pub fn euler_to_quaternion(euler: Vec3) -> Quaternion {
-        // x = p
-        // y = h
-        // z = b
         let hc = cos(euler.y / 2.0);
         let pc = cos(euler.x / 2.0);
         let bc = cos(euler.z / 2.0);
@@ -56,6 +167,124 @@ impl Quaternion {
             z: hs * ps * bc - hc * pc * bs,
         }
     }
+    // Inverse of euler_to_quaternion for unit quaternions. Pitch comes out
+    // within ±pi/2; looking straight up or down, the bank is folded into the
+    // heading.
+    pub fn quaternion_to_euler(&self) -> Vec3 {
+        let Quaternion { w, x, y, z } = *self;
+        let sp = -2.0 * (y * z + w * x);
+        if fabs(sp) > 0.9999999 {
+            return Vec3 {
+                x: FRAC_PI_2 * sp.signum(),
+                y: atan2(-2.0 * (x * z + w * y), 1.0 - 2.0 * (y * y + z * z)),
+                z: 0.0,
+            };
+        }
+        Vec3 {
+            x: asin(sp),
+            y: atan2(2.0 * (x * z - w * y), 1.0 - 2.0 * (x * x + y * y)),
+            z: atan2(2.0 * (x * y - w * z), 1.0 - 2.0 * (x * x + z * z)),
+        }
+    }
+
+    // Angles are about the x, y and z axes whatever the order, turning
+    // counterclockwise seen from the positive end of the axis
+    pub fn new_from_euler(angles: Vec3, order: EulerOrder) -> Quaternion {
+        order
+            .intrinsic_axes()
+            .iter()
+            .map(|&axis| Quaternion::new_from_axis_angle(unit_axis(axis), angles[axis]))
+            .fold(Quaternion::new(1.0, 0.0, 0.0, 0.0), |q, turn| q * turn)
+    }
+    // Inverse of new_from_euler for unit quaternions. The middle angle comes
+    // out within ±pi/2; when it is at either end, the last turn is folded into
+    // the first.
+    pub fn to_euler(self, order: EulerOrder) -> Vec3 {
+        let [i, j, k] = order.intrinsic_axes();
+        let m: [[f64; 3]; 3] = Mat3::from_quaternion(&self).rows;
+        // Cyclic orders like XYZ have the opposite signs from the rest
+        let sign: f64 = if (j + 3 - i) % 3 == 1 { 1.0 } else { -1.0 };
+
+        let mut angles: Vec3 = Vec3 {
+            x: 0.0,
+            y: 0.0,
+            z: 0.0,
+        };
+        let s = sign * m[i][k];
+        if fabs(s) > 0.9999999 {
+            angles[j] = FRAC_PI_2 * s.signum();
+            angles[i] = atan2(sign * m[k][j], m[j][j]);
+        } else {
+            angles[j] = asin(s);
+            angles[i] = atan2(-sign * m[j][k], m[k][k]);
+            angles[k] = atan2(-sign * m[i][j], m[i][i]);
+        }
+        angles
+    }
+
+    // Straight line between the two, normalized. Cheaper than slerp but
+    // uneven in speed.
+    pub fn nlerp(a: &Quaternion, b: &Quaternion, t: f64) -> Quaternion {
+        let b: Quaternion = if Quaternion::dot(a, b) < 0.0 { -*b } else { *b };
+        Quaternion {
+            w: a.w + (b.w - a.w) * t,
+            x: a.x + (b.x - a.x) * t,
+            y: a.y + (b.y - a.y) * t,
+            z: a.z + (b.z - a.z) * t,
+        }
+        .normalize()
+    }
+    // Turns from unit quaternion a to b at a steady rate, the short way round
+    pub fn slerp(a: &Quaternion, b: &Quaternion, t: f64) -> Quaternion {
+        let mut cos_theta = Quaternion::dot(a, b);
+        let b: Quaternion = if cos_theta < 0.0 {
+            cos_theta = -cos_theta;
+            -*b
+        } else {
+            *b
+        };
+        if cos_theta > PARALLEL {
+            return Quaternion::nlerp(a, &b, t);
+        }
+        let theta = acos(cos_theta);
+        let sa = sin((1.0 - t) * theta) / sin(theta);
+        let sb = sin(t * theta) / sin(theta);
+        Quaternion {
+            w: a.w * sa + b.w * sb,
+            x: a.x * sa + b.x * sb,
+            y: a.y * sa + b.y * sb,
+            z: a.z * sa + b.z * sb,
+        }
+    }
+
+    // Component-wise. q and -q are the same rotation but are not equal here.
+    pub fn approx_eq(&self, other: &Quaternion, tolerance: f64) -> bool {
+        fabs(self.w - other.w) <= tolerance
+            && fabs(self.x - other.x) <= tolerance
+            && fabs(self.y - other.y) <= tolerance
+            && fabs(self.z - other.z) <= tolerance
+    }
+}
+
+fn unit_axis(axis: usize) -> Vec3 {
+    Vec3 {
+        x: if axis == 0 { 1.0 } else { 0.0 },
+        y: if axis == 1 { 1.0 } else { 0.0 },
+        z: if axis == 2 { 1.0 } else { 0.0 },
+    }
+}
+
+impl ops::Neg for Quaternion {
+    type Output = Quaternion;
+
+    fn neg(self) -> Self {
+        Quaternion {
+            w: -self.w,
+            x: -self.x,
+            y: -self.y,
+            z: -self.z,
+        }
+    }
 }
 
 impl ops::Mul<Quaternion> for Quaternion {
@@ -70,3 +299,333 @@ impl ops::Mul<Quaternion> for Quaternion {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use libm::{cos, sin};
+
+    const ORDERS: [EulerOrder; 12] = [
+        EulerOrder::Intrinsic(Axes::XYZ),
+        EulerOrder::Intrinsic(Axes::XZY),
+        EulerOrder::Intrinsic(Axes::YXZ),
+        EulerOrder::Intrinsic(Axes::YZX),
+        EulerOrder::Intrinsic(Axes::ZXY),
+        EulerOrder::Intrinsic(Axes::ZYX),
+        EulerOrder::Extrinsic(Axes::XYZ),
+        EulerOrder::Extrinsic(Axes::XZY),
+        EulerOrder::Extrinsic(Axes::YXZ),
+        EulerOrder::Extrinsic(Axes::YZX),
+        EulerOrder::Extrinsic(Axes::ZXY),
+        EulerOrder::Extrinsic(Axes::ZYX),
+    ];
+
+    fn angles() -> Vec<Vec3> {
+        let mut angles: Vec<Vec3> = Vec::new();
+        for i in 0..200 {
+            let f = i as f64;
+            angles.push(Vec3 {
+                x: 3.1 * sin(f * 0.37),
+                y: 1.5 * sin(f * 0.91),
+                z: 3.1 * sin(f * 1.3),
+            });
+        }
+        angles
+    }
+
+    fn same_rotation(a: &Quaternion, b: &Quaternion) -> bool {
+        a.approx_eq(b, 1e-9) || a.approx_eq(&-*b, 1e-9)
+    }
+
+    fn rotation_matrix(axis: usize, angle: f64) -> Mat3 {
+        let (c, s) = (cos(angle), sin(angle));
+        match axis {
+            0 => Mat3::new([[1.0, 0.0, 0.0], [0.0, c, -s], [0.0, s, c]]),
+            1 => Mat3::new([[c, 0.0, s], [0.0, 1.0, 0.0], [-s, 0.0, c]]),
+            _ => Mat3::new([[c, -s, 0.0], [s, c, 0.0], [0.0, 0.0, 1.0]]),
+        }
+    }
+
+    #[test]
+    fn euler_round_trips() {
+        for order in ORDERS {
+            for angles in angles() {
+                // The middle angle has to stay within ±pi/2 to come back as is
+                let [_, j, _] = order.intrinsic_axes();
+                let mut angles: Vec3 = angles;
+                angles[j] = angles[j].clamp(-1.5, 1.5);
+
+                let q: Quaternion = Quaternion::new_from_euler(angles, order);
+                let back: Vec3 = q.to_euler(order);
+                assert!(
+                    back.approx_eq(&angles, 1e-9),
+                    "{:?}: {:?} came back as {:?}",
+                    order,
+                    angles,
+                    back
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn euler_round_trips_at_gimbal_lock() {
+        for order in ORDERS {
+            let [i, j, k] = order.intrinsic_axes();
+            for middle in [FRAC_PI_2, -FRAC_PI_2] {
+                let mut angles: Vec3 = Vec3 {
+                    x: 0.0,
+                    y: 0.0,
+                    z: 0.0,
+                };
+                (angles[i], angles[j], angles[k]) = (0.4, middle, -1.1);
+                let q: Quaternion = Quaternion::new_from_euler(angles, order);
+                let back: Quaternion = Quaternion::new_from_euler(q.to_euler(order), order);
+                assert!(same_rotation(&q, &back), "{:?} at {}", order, middle);
+            }
+        }
+    }
+
+    #[test]
+    fn euler_agrees_with_matrix_composition() {
+        for order in ORDERS {
+            for angles in angles() {
+                // Intrinsic turns multiply on the right, extrinsic on the left
+                let expected: Mat3 = match order {
+                    EulerOrder::Intrinsic(_) => order
+                        .intrinsic_axes()
+                        .iter()
+                        .fold(Mat3::identity(), |m, &axis| {
+                            m * rotation_matrix(axis, angles[axis])
+                        }),
+                    EulerOrder::Extrinsic(_) => {
+                        let [k, j, i] = order.intrinsic_axes();
+                        [i, j, k].iter().fold(Mat3::identity(), |m, &axis| {
+                            rotation_matrix(axis, angles[axis]) * m
+                        })
+                    }
+                };
+                let actual: Mat3 =
+                    Mat3::from_quaternion(&Quaternion::new_from_euler(angles, order));
+                for (row, expected_row) in actual.rows.iter().zip(expected.rows.iter()) {
+                    for (x, y) in row.iter().zip(expected_row.iter()) {
+                        assert!(fabs(x - y) < 1e-9, "{:?} at {:?}", order, angles);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn extrinsic_xyz_turns_about_world_axes() {
+        // A quarter turn about x then about the world z, as Blender would
+        let q: Quaternion = Quaternion::new_from_euler(
+            Vec3 {
+                x: FRAC_PI_2,
+                y: 0.0,
+                z: FRAC_PI_2,
+            },
+            EulerOrder::Extrinsic(Axes::XYZ),
+        );
+        // y goes to z under the first turn and stays there under the second
+        let p: Vec3 = q.rotate(&Vec3 {
+            x: 0.0,
+            y: 1.0,
+            z: 0.0,
+        });
+        assert!(p.approx_eq(
+            &Vec3 {
+                x: 0.0,
+                y: 0.0,
+                z: 1.0,
+            },
+            1e-12
+        ));
+    }
+
+    #[test]
+    fn legacy_euler_matches_negated_intrinsic_zxy() {
+        for angles in angles() {
+            let legacy: Quaternion = Quaternion::euler_to_quaternion(angles);
+            let ordered: Quaternion =
+                Quaternion::new_from_euler(-angles, EulerOrder::Intrinsic(Axes::ZXY));
+            assert!(same_rotation(&legacy, &ordered), "{:?}", angles);
+        }
+    }
+
+    fn about_y(angle: f64) -> Quaternion {
+        Quaternion::new_from_axis_angle(
+            Vec3 {
+                x: 0.0,
+                y: 1.0,
+                z: 0.0,
+            },
+            angle,
+        )
+    }
+
+    #[test]
+    fn slerp_midpoint() {
+        let (a, b) = (about_y(0.2), about_y(1.4));
+        assert!(Quaternion::slerp(&a, &b, 0.0).approx_eq(&a, 1e-12));
+        assert!(Quaternion::slerp(&a, &b, 1.0).approx_eq(&b, 1e-12));
+        assert!(Quaternion::slerp(&a, &b, 0.5).approx_eq(&about_y(0.8), 1e-12));
+        assert!(Quaternion::slerp(&a, &b, 0.25).approx_eq(&about_y(0.5), 1e-12));
+
+        // Close enough to fall back to nlerp
+        let c: Quaternion = about_y(0.21);
+        assert!(Quaternion::slerp(&a, &c, 0.5).approx_eq(&about_y(0.205), 1e-9));
+    }
+
+    #[test]
+    fn slerp_goes_the_short_way_round() {
+        // -b is the same rotation, and must not send the turn the long way
+        let (a, b) = (about_y(0.2), about_y(1.4));
+        let mid: Quaternion = Quaternion::slerp(&a, &-b, 0.5);
+        assert!(same_rotation(&mid, &about_y(0.8)));
+        assert!(same_rotation(
+            &Quaternion::nlerp(&a, &-b, 0.5),
+            &about_y(0.8)
+        ));
+
+        // Nearly half a turn each way, which meet at the half turn rather than at zero
+        let mid: Quaternion = Quaternion::slerp(&about_y(-3.0), &about_y(3.0), 0.5);
+        assert!(same_rotation(&mid, &about_y(std::f64::consts::PI)));
+    }
+
+    #[test]
+    fn from_to() {
+        let pairs: [(Vec3, Vec3); 5] = [
+            (
+                Vec3 {
+                    x: 1.0,
+                    y: 0.0,
+                    z: 0.0,
+                },
+                Vec3 {
+                    x: 0.0,
+                    y: 3.0,
+                    z: 0.0,
+                },
+            ),
+            (
+                Vec3 {
+                    x: 1.0,
+                    y: 2.0,
+                    z: 3.0,
+                },
+                Vec3 {
+                    x: -0.5,
+                    y: 0.1,
+                    z: 2.0,
+                },
+            ),
+            // Opposite vectors, along each axis and not
+            (
+                Vec3 {
+                    x: 1.0,
+                    y: 0.0,
+                    z: 0.0,
+                },
+                Vec3 {
+                    x: -1.0,
+                    y: 0.0,
+                    z: 0.0,
+                },
+            ),
+            (
+                Vec3 {
+                    x: 0.0,
+                    y: 0.0,
+                    z: 2.0,
+                },
+                Vec3 {
+                    x: 0.0,
+                    y: 0.0,
+                    z: -3.0,
+                },
+            ),
+            (
+                Vec3 {
+                    x: 1.0,
+                    y: 2.0,
+                    z: 3.0,
+                },
+                Vec3 {
+                    x: -2.0,
+                    y: -4.0,
+                    z: -6.0,
+                },
+            ),
+        ];
+        for (from, to) in pairs {
+            let q: Quaternion = Quaternion::new_from_to(from, to);
+            assert!((Quaternion::dot(&q, &q) - 1.0).abs() < 1e-12);
+            assert!(
+                q.rotate(&from.normalize())
+                    .approx_eq(&to.normalize(), 1e-12),
+                "{:?} to {:?}",
+                from,
+                to
+            );
+        }
+    }
+
+    #[test]
+    fn zero_vectors_turn_nothing() {
+        let identity = Quaternion::new(1.0, 0.0, 0.0, 0.0);
+        let zero = Vec3 {
+            x: 0.0,
+            y: 0.0,
+            z: 0.0,
+        };
+        let up = Vec3 {
+            x: 0.0,
+            y: 1.0,
+            z: 0.0,
+        };
+        assert!(Quaternion::new_from_axis_angle(zero, 1.0).approx_eq(&identity, 0.0));
+        assert!(Quaternion::new_from_to(zero, up).approx_eq(&identity, 0.0));
+        assert!(Quaternion::new_from_to(up, zero).approx_eq(&identity, 0.0));
+    }
+
+    #[test]
+    fn legacy_euler_round_trips() {
+        for angles in angles() {
+            let angles: Vec3 = Vec3 {
+                x: angles.x.clamp(-1.5, 1.5),
+                ..angles
+            };
+            let back: Vec3 = Quaternion::euler_to_quaternion(angles).quaternion_to_euler();
+            assert!(
+                back.approx_eq(&angles, 1e-9),
+                "{:?} came back as {:?}",
+                angles,
+                back
+            );
+        }
+
+        // Looking straight up or down the bank folds into the heading
+        for pitch in [FRAC_PI_2, -FRAC_PI_2] {
+            let q: Quaternion = Quaternion::euler_to_quaternion(Vec3 {
+                x: pitch,
+                y: 0.4,
+                z: -1.1,
+            });
+            let back: Vec3 = q.quaternion_to_euler();
+            assert_eq!(back.x, pitch);
+            assert_eq!(back.z, 0.0);
+            assert!(same_rotation(&q, &Quaternion::euler_to_quaternion(back)));
+        }
+    }
+
+    #[test]
+    fn inverse_of_a_non_unit_quaternion() {
+        let q = Quaternion::new(2.0, 1.0, -1.0, 0.5);
+        let identity = Quaternion::new(1.0, 0.0, 0.0, 0.0);
+        assert!((q * q.inverse()).approx_eq(&identity, 1e-12));
+        assert!((q.inverse() * q).approx_eq(&identity, 1e-12));
+        // The conjugate alone is off by the squared length
+        assert!((q * q.conjugate()).approx_eq(&Quaternion::new(6.25, 0.0, 0.0, 0.0), 1e-12));
+    }
+}
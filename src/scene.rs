@@ -0,0 +1,433 @@
+use crate::aabb::Aabb;
+use crate::bvh::Bvh;
+use crate::csg::Csg;
+use crate::instance::{Geometry, Instance};
+use crate::matrix::Mat4;
+use crate::mesh::Mesh;
+use crate::object::{Hit, Object, ObjectTrait};
+use crate::ray::Ray;
+use crate::rotation::Quaternion;
+use crate::sdf::SdfObject;
+use crate::transform::Transform;
+use crate::vector::Vec3;
+use std::rc::Rc;
+
+// Where a loaded scene asks to be looked at from
+#[derive(Debug, Copy, Clone)]
+pub struct View {
+    pub position: Vec3,
+    pub orientation: Quaternion,
+    pub yfov: Option<f64>, // vertical field of view in radians
+}
+
+// Part of the scene graph, placed relative to its parent. Parents come before
+// their children, so a node's parent is always added first.
+#[derive(Debug, Clone)]
+pub struct SceneNode {
+    pub name: Option<String>,
+    pub transform: Transform,
+    pub geometry: Option<Rc<Geometry>>, // None for nodes that only group others
+    parent: Option<usize>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Scene {
+    pub objects: Vec<Object>,
+    pub meshes: Vec<Mesh>,
+    // Few enough to test one by one, each culled by its bounds
+    pub sdfs: Vec<SdfObject>,
+    pub csgs: Vec<Csg>,
+    pub nodes: Vec<SceneNode>,
+    instances: Vec<Instance>, // the nodes with geometry, placed in the world
+    pub view: Option<View>,
+    bvh: Option<Bvh>,
+}
+
+impl Scene {
+    pub fn new() -> Scene {
+        Scene {
+            objects: Vec::new(),
+            meshes: Vec::new(),
+            sdfs: Vec::new(),
+            csgs: Vec::new(),
+            nodes: Vec::new(),
+            instances: Vec::new(),
+            view: None,
+            bvh: None,
+        }
+    }
+
+    pub fn add(&mut self, object: Object) -> usize {
+        self.objects.push(object);
+        self.objects.len() - 1
+    }
+    pub fn add_mesh(&mut self, mesh: Mesh) -> usize {
+        self.meshes.push(mesh);
+        self.meshes.len() - 1
+    }
+    pub fn add_sdf(&mut self, sdf: SdfObject) -> usize {
+        self.sdfs.push(sdf);
+        self.sdfs.len() - 1
+    }
+    pub fn add_csg(&mut self, csg: Csg) -> usize {
+        self.csgs.push(csg);
+        self.csgs.len() - 1
+    }
+    // Call update_instances once done adding nodes
+    pub fn add_node(
+        &mut self,
+        parent: Option<usize>,
+        transform: Transform,
+        geometry: Option<Rc<Geometry>>,
+    ) -> usize {
+        assert!(parent.is_none_or(|parent| parent < self.nodes.len()));
+        self.nodes.push(SceneNode {
+            name: None,
+            transform,
+            geometry,
+            parent,
+        });
+        self.nodes.len() - 1
+    }
+
+    // Places every node's geometry in the world, composing each transform with
+    // its parents', and brings the BVH up to date. Call it again after changing
+    // the nodes.
+    pub fn update_instances(&mut self) {
+        let mut world: Vec<(Mat4, Mat4)> = Vec::with_capacity(self.nodes.len());
+        self.instances.clear();
+        for node in &self.nodes {
+            let (mut matrix, mut inverse) = (node.transform.matrix(), node.transform.inverse());
+            if let Some(parent) = node.parent {
+                let (parent_matrix, parent_inverse) = world[parent];
+                matrix = parent_matrix * matrix;
+                inverse = inverse * parent_inverse;
+            }
+            world.push((matrix, inverse));
+            if let Some(geometry) = &node.geometry {
+                self.instances
+                    .push(Instance::from_matrices(geometry.clone(), matrix, inverse));
+            }
+        }
+        self.update_bvh();
+    }
+
+    // Bounds of all the meshes, placed by nodes or not. None if there are none.
+    pub fn mesh_bounds(&self) -> Option<Aabb> {
+        let placed = self
+            .instances
+            .iter()
+            .filter(|instance| matches!(**instance.geometry(), Geometry::Mesh(..)))
+            .filter_map(|instance| instance.bounds());
+        self.meshes
+            .iter()
+            .map(|mesh| mesh.bounds())
+            .chain(placed)
+            .reduce(|bounds, other| bounds.union(&other))
+    }
+
+    // Builds the BVH that `intersect` uses from then on. Call it again after
+    // changing the objects or meshes.
+    pub fn build_bvh(&mut self) {
+        self.bvh = Some(Bvh::build(&self.objects, &self.meshes, &self.instances));
+    }
+
+    // Brings the BVH up to date after objects, meshes or instances have moved. The
+    // tree is refitted in place, and only rebuilt once that has made it too slow
+    // to traverse.
+    pub fn update_bvh(&mut self) {
+        let refitted: bool = match &mut self.bvh {
+            Some(bvh) => bvh.refit(&self.objects, &self.meshes, &self.instances) && !bvh.degraded(),
+            None => false,
+        };
+        if !refitted {
+            self.build_bvh();
+        }
+    }
+
+    // Closest hit over everything in the scene
+    pub fn intersect(&self, r: &Ray) -> Option<Hit> {
+        let closest: Option<Hit> = match &self.bvh {
+            Some(bvh) => bvh.intersect(&self.objects, &self.meshes, &self.instances, r),
+            None => self.intersect_objects(r),
+        };
+        closer(closest, self.intersect_solids(r))
+    }
+
+    // Tests everything in turn, without the BVH
+    #[cfg(test)]
+    pub fn intersect_brute_force(&self, r: &Ray) -> Option<Hit> {
+        closer(self.intersect_objects(r), self.intersect_solids(r))
+    }
+
+    // Closest hit over the objects, meshes and instances, which the BVH covers
+    fn intersect_objects(&self, r: &Ray) -> Option<Hit> {
+        let hits = self
+            .objects
+            .iter()
+            .filter_map(|object| object.intersect(r))
+            .chain(self.meshes.iter().filter_map(|mesh| mesh.intersect(r)))
+            .chain(
+                self.instances
+                    .iter()
+                    .filter_map(|instance| instance.intersect(r)),
+            );
+        closer(None, hits)
+    }
+
+    // Hits on the distance fields and CSG solids, which the BVH leaves out
+    fn intersect_solids<'a>(&'a self, r: &'a Ray) -> impl Iterator<Item = Hit> + 'a {
+        self.sdfs
+            .iter()
+            .filter_map(|sdf| sdf.intersect(r))
+            .chain(self.csgs.iter().filter_map(|csg| csg.intersect(r)))
+    }
+}
+
+// Nearest of a hit found so far and some more
+fn closer(mut closest: Option<Hit>, hits: impl Iterator<Item = Hit>) -> Option<Hit> {
+    for hit in hits {
+        if closest.is_none_or(|c| hit.t < c.t) {
+            closest = Some(hit);
+        }
+    }
+    closest
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use libm::fabs;
+
+    fn ball(radius: f64) -> Option<Rc<Geometry>> {
+        Some(Rc::new(Geometry::new_object(Object::new_sphere(
+            &Vec3 {
+                x: 0.0,
+                y: 0.0,
+                z: 0.0,
+            },
+            &radius,
+        ))))
+    }
+
+    // Fires a fan of rays from the origin and checks the BVH finds the same
+    // closest hits as testing everything in turn
+    fn assert_matches_brute_force(scene: &Scene) {
+        let mut hits: usize = 0;
+        for i in 0..40 {
+            for j in 0..40 {
+                let r = Ray::new(
+                    Vec3 {
+                        x: 0.0,
+                        y: 0.0,
+                        z: 0.0,
+                    },
+                    Vec3 {
+                        x: i as f64 / 20.0 - 1.0,
+                        y: j as f64 / 20.0 - 1.0,
+                        z: -1.0,
+                    },
+                );
+                match (scene.intersect(&r), scene.intersect_brute_force(&r)) {
+                    (None, None) => {}
+                    (Some(actual), Some(expected)) => {
+                        hits += 1;
+                        assert!((actual.t - expected.t).abs() < 1e-9, "{:?}", r);
+                    }
+                    (actual, expected) => panic!("{:?} != {:?} for {:?}", actual, expected, r),
+                }
+            }
+        }
+        assert!(hits > 100, "only {} rays hit anything", hits);
+    }
+
+    // A unit ray from the origin towards `center` meets a ball of `radius` there
+    fn assert_ball_at(scene: &Scene, center: Vec3, radius: f64) {
+        let r = Ray::new(
+            Vec3 {
+                x: 0.0,
+                y: 0.0,
+                z: 0.0,
+            },
+            center.normalize(),
+        );
+        let hit: Hit = scene.intersect(&r).unwrap();
+        assert!((hit.t - (center.length() - radius)).abs() < 1e-9);
+    }
+
+    #[test]
+    fn children_follow_their_parents() {
+        let mut scene = Scene::new();
+        let parent = scene.add_node(
+            None,
+            Transform::new(
+                Vec3 {
+                    x: 0.0,
+                    y: 0.0,
+                    z: -5.0,
+                },
+                Quaternion::new(1.0, 0.0, 0.0, 0.0),
+                Vec3 {
+                    x: 2.0,
+                    y: 2.0,
+                    z: 2.0,
+                },
+            )
+            .rotate(Vec3 {
+                x: 0.0,
+                y: std::f64::consts::FRAC_PI_2,
+                z: 0.0,
+            }),
+            None,
+        );
+        scene.add_node(
+            Some(parent),
+            Transform::identity().translate(Vec3 {
+                x: 1.0,
+                y: 0.0,
+                z: 0.0,
+            }),
+            ball(0.5),
+        );
+        scene.update_instances();
+
+        // The child's offset is scaled and turned by the parent, and so is its size
+        let offset: Vec3 = scene.nodes[parent].transform.rotation.rotate(&Vec3 {
+            x: 2.0,
+            y: 0.0,
+            z: 0.0,
+        });
+        assert!(fabs(offset.x) < 1e-9);
+        assert_ball_at(
+            &scene,
+            scene.nodes[parent].transform.translation + offset,
+            1.0,
+        );
+
+        // And goes where the parent goes once the instances are updated
+        scene.nodes[parent].transform = scene.nodes[parent].transform.translate(Vec3 {
+            x: 0.0,
+            y: 3.0,
+            z: 0.0,
+        });
+        scene.update_instances();
+        assert_ball_at(
+            &scene,
+            scene.nodes[parent].transform.translation + offset,
+            1.0,
+        );
+    }
+
+    #[test]
+    fn instances_go_through_the_bvh() {
+        let mut scene = Scene::new();
+        // A ring of balls carried round by their parent, in front of an
+        // infinite wall that's an instance too
+        let ring = scene.add_node(
+            None,
+            Transform::identity().translate(Vec3 {
+                x: 0.0,
+                y: 0.0,
+                z: -8.0,
+            }),
+            None,
+        );
+        for i in 0..12 {
+            let angle: f64 = i as f64 * std::f64::consts::PI / 6.0;
+            scene.add_node(
+                Some(ring),
+                Transform::identity().translate(Vec3 {
+                    x: 3.0 * libm::cos(angle),
+                    y: 3.0 * libm::sin(angle),
+                    z: 0.0,
+                }),
+                ball(0.8),
+            );
+        }
+        scene.add_node(
+            None,
+            Transform::identity().translate(Vec3 {
+                x: 0.0,
+                y: 0.0,
+                z: -20.0,
+            }),
+            Some(Rc::new(Geometry::new_object(Object::new_plane(
+                &Vec3 {
+                    x: 0.0,
+                    y: 0.0,
+                    z: 0.0,
+                },
+                &Vec3 {
+                    x: 0.0,
+                    y: 0.0,
+                    z: 1.0,
+                },
+            )))),
+        );
+        scene.update_instances();
+        assert_matches_brute_force(&scene);
+
+        for _ in 0..5 {
+            scene.nodes[ring].transform = scene.nodes[ring].transform.rotate(Vec3 {
+                x: 0.3,
+                y: 0.2,
+                z: 0.5,
+            });
+            scene.update_instances();
+            assert_matches_brute_force(&scene);
+        }
+    }
+
+    #[test]
+    fn moved_objects_are_found_after_update_bvh() {
+        let mut scene = Scene::new();
+        for i in 0..30 {
+            scene.add(Object::new_sphere(
+                &Vec3 {
+                    x: 5.0 * libm::sin(i as f64 * 1.7),
+                    y: 2.5 * libm::sin(i as f64 * 2.3),
+                    z: -9.0 + 3.0 * libm::sin(i as f64 * 0.9),
+                },
+                &0.6,
+            ));
+        }
+        scene.add(Object::new_plane(
+            &Vec3 {
+                x: 0.0,
+                y: -3.0,
+                z: 0.0,
+            },
+            &Vec3 {
+                x: 0.0,
+                y: 1.0,
+                z: 0.0,
+            },
+        ));
+        scene.build_bvh();
+        assert_matches_brute_force(&scene);
+
+        // A small move is refitted
+        scene.objects[0] = scene.objects[0].move_to(Vec3 {
+            x: 0.4,
+            y: -0.2,
+            z: 0.0,
+        });
+        scene.objects[17] = scene.objects[17].move_to(Vec3 {
+            x: -0.3,
+            y: 0.0,
+            z: 0.5,
+        });
+        scene.update_bvh();
+        assert_matches_brute_force(&scene);
+
+        // Reversing the balls stretches the boxes across the scene, which is
+        // too slow to keep, so the tree is rebuilt
+        scene.objects[..30].reverse();
+        let mut refitted: Bvh = scene.bvh.clone().unwrap();
+        assert!(refitted.refit(&scene.objects, &scene.meshes, &scene.instances));
+        assert!(refitted.degraded());
+        scene.update_bvh();
+        assert!(!scene.bvh.as_ref().unwrap().degraded());
+        assert_matches_brute_force(&scene);
+    }
+}
@@ -0,0 +1,668 @@
+use crate::aabb::Aabb;
+use crate::fractal;
+use crate::object::{Hit, ObjectTrait};
+use crate::ray::Ray;
+use crate::rotation::Quaternion;
+use crate::vector::Vec3;
+use libm::{fabs, sqrt};
+use std::fmt;
+use std::rc::Rc;
+
+const MAX_STEPS: usize = 256;
+const EPSILON: f64 = 1e-4; // how close a step has to get to count as a hit
+const ORIGIN: Vec3 = Vec3 {
+    x: 0.0,
+    y: 0.0,
+    z: 0.0,
+};
+const NORMAL_EPSILON: f64 = 1e-4; // offset of the central differences
+
+// A user-supplied signed distance function
+#[derive(Clone)]
+pub struct DistanceFn(Rc<dyn Fn(&Vec3) -> f64>);
+
+impl fmt::Debug for DistanceFn {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "DistanceFn")
+    }
+}
+
+// Signed distance expression tree, negative inside. Subtrees are shared, so
+// cloning a shape is cheap.
+#[derive(Debug, Clone)]
+pub enum Sdf {
+    Sphere {
+        center: Vec3,
+        radius: f64,
+    },
+    Box {
+        center: Vec3,
+        half_size: Vec3,
+    },
+    RoundBox {
+        center: Vec3,
+        half_size: Vec3, // including the rounding
+        radius: f64,
+    },
+    Torus {
+        center: Vec3,
+        major_radius: f64,
+        minor_radius: f64, // the ring lies in the xz plane
+    },
+    Capsule {
+        a: Vec3,
+        b: Vec3,
+        radius: f64,
+    },
+    Union(Rc<Sdf>, Rc<Sdf>),
+    Intersection(Rc<Sdf>, Rc<Sdf>),
+    Difference(Rc<Sdf>, Rc<Sdf>),
+    // k is the width of the blend
+    SmoothUnion(Rc<Sdf>, Rc<Sdf>, f64),
+    SmoothIntersection(Rc<Sdf>, Rc<Sdf>, f64),
+    SmoothDifference(Rc<Sdf>, Rc<Sdf>, f64),
+    // Fractals around the origin, see the fractal module for their extents
+    Mandelbulb {
+        power: f64,
+        iterations: usize,
+    },
+    MengerSponge {
+        iterations: usize,
+    },
+    SierpinskiTetrahedron {
+        iterations: usize,
+    },
+    // Scales, then rotates, then translates the shape
+    Transform {
+        shape: Rc<Sdf>,
+        translation: Vec3,
+        rotation: Quaternion,
+        scale: f64,
+    },
+    // The function has to stay inside the bounds and must not overestimate
+    // the distance, or sphere tracing steps through the surface
+    Function {
+        distance: DistanceFn,
+        bounds: Aabb,
+    },
+}
+
+impl Sdf {
+    pub fn new_sphere(center: &Vec3, radius: f64) -> Self {
+        Sdf::Sphere {
+            center: *center,
+            radius,
+        }
+    }
+    pub fn new_box(center: &Vec3, half_size: &Vec3) -> Self {
+        Sdf::Box {
+            center: *center,
+            half_size: *half_size,
+        }
+    }
+    pub fn new_round_box(center: &Vec3, half_size: &Vec3, radius: f64) -> Self {
+        Sdf::RoundBox {
+            center: *center,
+            half_size: *half_size,
+            radius,
+        }
+    }
+    pub fn new_torus(center: &Vec3, major_radius: f64, minor_radius: f64) -> Self {
+        Sdf::Torus {
+            center: *center,
+            major_radius,
+            minor_radius,
+        }
+    }
+    pub fn new_capsule(a: &Vec3, b: &Vec3, radius: f64) -> Self {
+        Sdf::Capsule {
+            a: *a,
+            b: *b,
+            radius,
+        }
+    }
+    pub fn new_mandelbulb(power: f64, iterations: usize) -> Self {
+        Sdf::Mandelbulb { power, iterations }
+    }
+    pub fn new_menger_sponge(iterations: usize) -> Self {
+        Sdf::MengerSponge { iterations }
+    }
+    pub fn new_sierpinski_tetrahedron(iterations: usize) -> Self {
+        Sdf::SierpinskiTetrahedron { iterations }
+    }
+    pub fn new_function(distance: impl Fn(&Vec3) -> f64 + 'static, bounds: Aabb) -> Self {
+        Sdf::Function {
+            distance: DistanceFn(Rc::new(distance)),
+            bounds,
+        }
+    }
+
+    pub fn union(self, other: Sdf) -> Self {
+        Sdf::Union(Rc::new(self), Rc::new(other))
+    }
+    pub fn intersection(self, other: Sdf) -> Self {
+        Sdf::Intersection(Rc::new(self), Rc::new(other))
+    }
+    pub fn difference(self, other: Sdf) -> Self {
+        Sdf::Difference(Rc::new(self), Rc::new(other))
+    }
+    pub fn smooth_union(self, other: Sdf, k: f64) -> Self {
+        Sdf::SmoothUnion(Rc::new(self), Rc::new(other), k)
+    }
+    pub fn smooth_intersection(self, other: Sdf, k: f64) -> Self {
+        Sdf::SmoothIntersection(Rc::new(self), Rc::new(other), k)
+    }
+    pub fn smooth_difference(self, other: Sdf, k: f64) -> Self {
+        Sdf::SmoothDifference(Rc::new(self), Rc::new(other), k)
+    }
+    pub fn translate(self, v: Vec3) -> Self {
+        self.transform(v, Quaternion::new(1.0, 0.0, 0.0, 0.0), 1.0)
+    }
+    pub fn rotate(self, euler: Vec3) -> Self {
+        self.transform(ORIGIN, Quaternion::euler_to_quaternion(euler), 1.0)
+    }
+    // Only uniform scales keep the distances exact
+    pub fn scale(self, scale: f64) -> Self {
+        self.transform(ORIGIN, Quaternion::new(1.0, 0.0, 0.0, 0.0), scale)
+    }
+    fn transform(self, translation: Vec3, rotation: Quaternion, scale: f64) -> Self {
+        Sdf::Transform {
+            shape: Rc::new(self),
+            translation,
+            rotation,
+            scale,
+        }
+    }
+
+    pub fn distance(&self, p: &Vec3) -> f64 {
+        match self {
+            Sdf::Sphere { center, radius } => (*p - *center).length() - radius,
+            Sdf::Box { center, half_size } => box_distance(&(*p - *center), half_size),
+            Sdf::RoundBox {
+                center,
+                half_size,
+                radius,
+            } => {
+                let inner: Vec3 = Vec3 {
+                    x: (half_size.x - radius).max(0.0),
+                    y: (half_size.y - radius).max(0.0),
+                    z: (half_size.z - radius).max(0.0),
+                };
+                box_distance(&(*p - *center), &inner) - radius
+            }
+            Sdf::Torus {
+                center,
+                major_radius,
+                minor_radius,
+            } => {
+                let q: Vec3 = *p - *center;
+                let ring: f64 = sqrt(q.x * q.x + q.z * q.z) - major_radius;
+                sqrt(ring * ring + q.y * q.y) - minor_radius
+            }
+            Sdf::Capsule { a, b, radius } => {
+                let pa: Vec3 = *p - *a;
+                let ba: Vec3 = *b - *a;
+                let h: f64 = (Vec3::dot(&pa, &ba) / Vec3::dot(&ba, &ba)).clamp(0.0, 1.0);
+                (pa - ba * h).length() - radius
+            }
+            Sdf::Union(a, b) => a.distance(p).min(b.distance(p)),
+            Sdf::Intersection(a, b) => a.distance(p).max(b.distance(p)),
+            Sdf::Difference(a, b) => a.distance(p).max(-b.distance(p)),
+            Sdf::SmoothUnion(a, b, k) => smooth_min(a.distance(p), b.distance(p), *k),
+            Sdf::SmoothIntersection(a, b, k) => -smooth_min(-a.distance(p), -b.distance(p), *k),
+            Sdf::SmoothDifference(a, b, k) => -smooth_min(-a.distance(p), b.distance(p), *k),
+            Sdf::Mandelbulb { power, iterations } => fractal::mandelbulb(p, *power, *iterations),
+            Sdf::MengerSponge { iterations } => fractal::menger_sponge(p, *iterations),
+            Sdf::SierpinskiTetrahedron { iterations } => {
+                fractal::sierpinski_tetrahedron(p, *iterations)
+            }
+            Sdf::Transform {
+                shape,
+                translation,
+                rotation,
+                scale,
+            } => {
+                let local: Vec3 = rotation.conjugate().rotate(&(*p - *translation)) / *scale;
+                shape.distance(&local) * scale
+            }
+            Sdf::Function {
+                distance,
+                bounds: _,
+            } => (distance.0)(p),
+        }
+    }
+
+    // Box the surface stays inside, None if the shape is empty
+    pub fn bounds(&self) -> Option<Aabb> {
+        match self {
+            Sdf::Sphere { center, radius } => Some(cube_around(center, *radius)),
+            Sdf::Box { center, half_size }
+            | Sdf::RoundBox {
+                center,
+                half_size,
+                radius: _,
+            } => Some(Aabb::new(*center - *half_size, *center + *half_size)),
+            Sdf::Torus {
+                center,
+                major_radius,
+                minor_radius,
+            } => {
+                let extent: Vec3 = Vec3 {
+                    x: major_radius + minor_radius,
+                    y: *minor_radius,
+                    z: major_radius + minor_radius,
+                };
+                Some(Aabb::new(*center - extent, *center + extent))
+            }
+            Sdf::Capsule { a, b, radius } => {
+                Some(cube_around(a, *radius).union(&cube_around(b, *radius)))
+            }
+            Sdf::Mandelbulb {
+                power: _,
+                iterations: _,
+            } => Some(cube_around(&ORIGIN, fractal::BAILOUT)),
+            Sdf::MengerSponge { iterations: _ } | Sdf::SierpinskiTetrahedron { iterations: _ } => {
+                Some(cube_around(&ORIGIN, 1.0))
+            }
+            Sdf::Union(a, b) => union_bounds(a.bounds(), b.bounds(), 0.0),
+            Sdf::SmoothUnion(a, b, k) => union_bounds(a.bounds(), b.bounds(), k / 4.0),
+            Sdf::Intersection(a, b) | Sdf::SmoothIntersection(a, b, _) => {
+                let (a, b) = (a.bounds()?, b.bounds()?);
+                let overlap: Aabb = Aabb::new(
+                    Vec3 {
+                        x: a.min.x.max(b.min.x),
+                        y: a.min.y.max(b.min.y),
+                        z: a.min.z.max(b.min.z),
+                    },
+                    Vec3 {
+                        x: a.max.x.min(b.max.x),
+                        y: a.max.y.min(b.max.y),
+                        z: a.max.z.min(b.max.z),
+                    },
+                );
+                if overlap.min.x > overlap.max.x
+                    || overlap.min.y > overlap.max.y
+                    || overlap.min.z > overlap.max.z
+                {
+                    return None;
+                }
+                Some(overlap)
+            }
+            // Cutting only ever removes from the first shape
+            Sdf::Difference(a, _) | Sdf::SmoothDifference(a, _, _) => a.bounds(),
+            Sdf::Transform {
+                shape,
+                translation,
+                rotation,
+                scale,
+            } => Some(
+                shape
+                    .bounds()?
+                    .transformed(|corner| rotation.rotate(&(*corner * *scale)) + *translation),
+            ),
+            Sdf::Function {
+                distance: _,
+                bounds,
+            } => Some(*bounds),
+        }
+    }
+
+    // Gradient of the distance by central differences, which is the outward
+    // normal on the surface
+    pub fn normal(&self, p: &Vec3) -> Vec3 {
+        let axis = |x: f64, y: f64, z: f64| {
+            let offset: Vec3 = Vec3 { x, y, z } * NORMAL_EPSILON;
+            self.distance(&(*p + offset)) - self.distance(&(*p - offset))
+        };
+        let gradient: Vec3 = Vec3 {
+            x: axis(1.0, 0.0, 0.0),
+            y: axis(0.0, 1.0, 0.0),
+            z: axis(0.0, 0.0, 1.0),
+        };
+        gradient.normalize()
+    }
+}
+
+// A distance expression placed in the scene. Moving and rotating it only
+// changes where its frame sits, so animating it never grows the tree.
+#[derive(Debug, Clone)]
+pub struct SdfObject {
+    shape: Sdf,
+    position: Vec3,
+    orientation: Quaternion,
+    local_bounds: Option<Aabb>,
+}
+
+impl SdfObject {
+    pub fn new(shape: Sdf) -> SdfObject {
+        let local_bounds: Option<Aabb> = shape.bounds();
+        SdfObject {
+            shape,
+            position: ORIGIN,
+            orientation: Quaternion::new(1.0, 0.0, 0.0, 0.0),
+            local_bounds,
+        }
+    }
+
+    pub fn bounds(&self) -> Option<Aabb> {
+        Some(
+            self.local_bounds?
+                .transformed(|corner| self.orientation.rotate(corner) + self.position),
+        )
+    }
+
+    // Keeps the shape where it was, for animating its parameters
+    pub fn set_shape(&mut self, shape: Sdf) {
+        self.local_bounds = shape.bounds();
+        self.shape = shape;
+    }
+
+    // Sphere traces the shape's frame, from where the ray enters its bounds to
+    // where it leaves them. Returns the ray parameter, the local hit point and
+    // how many steps it took to get there.
+    fn trace(&self, r: &Ray) -> Option<(f64, Vec3, usize)> {
+        let inverse: Quaternion = self.orientation.conjugate();
+        let origin: Vec3 = inverse.rotate(&(r.origin() - self.position));
+        let direction: Vec3 = inverse.rotate(&r.direction());
+        let (t_near, t_far) = self
+            .local_bounds?
+            .interval(&Ray::new(origin, direction), f64::INFINITY)?;
+
+        // March in distance units, since that is what the shape measures
+        let speed: f64 = direction.length();
+        let unit: Vec3 = direction / speed;
+        let mut t: f64 = t_near * speed;
+        for steps in 0..MAX_STEPS {
+            let point: Vec3 = origin + unit * t;
+            // The magnitude also finds the way out when starting inside
+            let distance: f64 = fabs(self.shape.distance(&point));
+            if distance < EPSILON {
+                return Some((t / speed, point, steps));
+            }
+            t += distance;
+            if t > t_far * speed {
+                return None;
+            }
+        }
+        None
+    }
+}
+
+impl ObjectTrait for SdfObject {
+    fn intersect(&self, r: &Ray) -> Option<Hit> {
+        let (t, local, steps) = self.trace(r)?;
+        if t <= f64::EPSILON {
+            return None;
+        }
+        Some(Hit {
+            t,
+            point: r.origin() + r.direction() * t,
+            normal: self.orientation.rotate(&self.shape.normal(&local)),
+            u: 0.0,
+            v: 0.0,
+            // Rays that creep along many nearby surfaces are heading into a
+            // crevice, which is where ambient light can't reach either
+            occlusion: steps as f64 / MAX_STEPS as f64,
+        })
+    }
+    fn move_to(&self, v: Vec3) -> Self {
+        SdfObject {
+            position: self.position + v,
+            ..self.clone()
+        }
+    }
+    fn rotate_around_center(&self, euler: Vec3) -> Self {
+        match self.bounds() {
+            Some(bounds) => self.rotate_around_point(euler, bounds.center()),
+            None => self.clone(),
+        }
+    }
+    fn rotate_around_point(&self, euler: Vec3, point: Vec3) -> Self {
+        let quaternion = Quaternion::euler_to_quaternion(euler);
+        SdfObject {
+            position: self.position.rotate_around_point(&point, &quaternion),
+            orientation: (quaternion * self.orientation).normalize(),
+            ..self.clone()
+        }
+    }
+}
+
+// Distance to a box centered on the origin
+fn box_distance(p: &Vec3, half_size: &Vec3) -> f64 {
+    let q: Vec3 = Vec3 {
+        x: fabs(p.x) - half_size.x,
+        y: fabs(p.y) - half_size.y,
+        z: fabs(p.z) - half_size.z,
+    };
+    let outside: Vec3 = Vec3 {
+        x: q.x.max(0.0),
+        y: q.y.max(0.0),
+        z: q.z.max(0.0),
+    };
+    outside.length() + q.x.max(q.y).max(q.z).min(0.0)
+}
+
+// Polynomial smooth minimum, which dips at most k / 4 below the plain minimum
+fn smooth_min(a: f64, b: f64, k: f64) -> f64 {
+    if k <= 0.0 {
+        return a.min(b);
+    }
+    let h: f64 = (k - fabs(a - b)).max(0.0) / k;
+    a.min(b) - h * h * k / 4.0
+}
+
+fn cube_around(center: &Vec3, radius: f64) -> Aabb {
+    let extent: Vec3 = Vec3 {
+        x: radius,
+        y: radius,
+        z: radius,
+    };
+    Aabb::new(*center - extent, *center + extent)
+}
+
+fn union_bounds(a: Option<Aabb>, b: Option<Aabb>, padding: f64) -> Option<Aabb> {
+    let bounds: Aabb = match (a, b) {
+        (Some(a), Some(b)) => a.union(&b),
+        (Some(bounds), None) | (None, Some(bounds)) => bounds,
+        (None, None) => return None,
+    };
+    let padding: Vec3 = Vec3 {
+        x: padding,
+        y: padding,
+        z: padding,
+    };
+    Some(Aabb::new(bounds.min - padding, bounds.max + padding))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn point(x: f64, y: f64, z: f64) -> Vec3 {
+        Vec3 { x, y, z }
+    }
+
+    fn assert_distance(shape: &Sdf, p: Vec3, expected: f64) {
+        let distance: f64 = shape.distance(&p);
+        assert!(
+            fabs(distance - expected) < 1e-9,
+            "{} at {:?}, expected {}",
+            distance,
+            p,
+            expected
+        );
+    }
+
+    #[test]
+    fn primitive_distances() {
+        let sphere = Sdf::new_sphere(&point(1.0, 0.0, 0.0), 1.0);
+        assert_distance(&sphere, point(4.0, 0.0, 0.0), 2.0);
+        assert_distance(&sphere, point(1.0, 0.0, 0.0), -1.0);
+
+        let cuboid = Sdf::new_box(&ORIGIN, &point(1.0, 2.0, 3.0));
+        assert_distance(&cuboid, point(3.0, 0.0, 0.0), 2.0);
+        assert_distance(&cuboid, point(2.0, 3.0, 0.0), sqrt(2.0));
+        assert_distance(&cuboid, ORIGIN, -1.0);
+
+        // Flat faces stay where the box's were, the edges round off
+        let round = Sdf::new_round_box(&ORIGIN, &point(1.0, 1.0, 1.0), 0.25);
+        assert_distance(&round, point(2.0, 0.0, 0.0), 1.0);
+        assert_distance(&round, point(2.0, 2.0, 0.0), 1.25 * sqrt(2.0) - 0.25);
+
+        let torus = Sdf::new_torus(&ORIGIN, 2.0, 0.5);
+        assert_distance(&torus, point(2.0, 1.0, 0.0), 0.5);
+        assert_distance(&torus, point(0.0, 0.0, -2.0), -0.5);
+        assert_distance(&torus, ORIGIN, 1.5);
+
+        let capsule = Sdf::new_capsule(&ORIGIN, &point(0.0, 2.0, 0.0), 0.5);
+        assert_distance(&capsule, point(1.0, 1.0, 0.0), 0.5);
+        assert_distance(&capsule, point(0.0, 3.0, 0.0), 0.5);
+        assert_distance(&capsule, point(0.0, -1.0, 0.0), 0.5);
+    }
+
+    #[test]
+    fn boolean_combinators() {
+        let a = || Sdf::new_sphere(&point(-1.0, 0.0, 0.0), 1.0);
+        let b = || Sdf::new_sphere(&point(1.0, 0.0, 0.0), 1.0);
+        let union: Sdf = a().union(b());
+        let intersection: Sdf = a().intersection(b());
+        let difference: Sdf = a().difference(b());
+
+        assert_distance(&union, point(2.0, 0.0, 0.0), 0.0);
+        assert_distance(&intersection, point(2.0, 0.0, 0.0), 2.0);
+        assert_distance(&intersection, ORIGIN, 0.0);
+        assert_distance(&difference, point(-1.0, 0.0, 0.0), -1.0);
+        // Inside b is outside the difference, by as far as it is from b's surface
+        assert_distance(&difference, point(1.0, 0.0, 0.0), 1.0);
+    }
+
+    #[test]
+    fn smooth_combinators() {
+        let a = || Sdf::new_sphere(&point(-1.0, 0.0, 0.0), 1.0);
+        let b = || Sdf::new_sphere(&point(1.0, 0.0, 0.0), 1.0);
+        let k: f64 = 0.5;
+        let union: Sdf = a().smooth_union(b(), k);
+        let intersection: Sdf = a().smooth_intersection(b(), k);
+        let difference: Sdf = a().smooth_difference(b(), k);
+
+        // Halfway between, both are the same distance away and the blend dips
+        // the furthest, by k / 4
+        let between: Vec3 = point(0.0, 3.0, 0.0);
+        let d: f64 = sqrt(10.0) - 1.0;
+        assert_distance(&union, between, d - k / 4.0);
+        assert_distance(&intersection, between, d + k / 4.0);
+
+        // Far from the seam they're the plain operations
+        for p in [point(-2.5, 0.0, 0.0), point(3.0, 1.0, 0.0)] {
+            assert_distance(&union, p, a().union(b()).distance(&p));
+            assert_distance(&intersection, p, a().intersection(b()).distance(&p));
+            assert_distance(&difference, p, a().difference(b()).distance(&p));
+        }
+    }
+
+    #[test]
+    fn transforms_scale_the_distance() {
+        let sphere = Sdf::new_sphere(&ORIGIN, 1.0).scale(2.0);
+        assert_distance(&sphere, point(5.0, 0.0, 0.0), 3.0);
+        assert_distance(&sphere, ORIGIN, -2.0);
+
+        // A long box stood on end and moved up
+        let post = Sdf::new_box(&ORIGIN, &point(3.0, 0.5, 0.5))
+            .rotate(point(0.0, 0.0, std::f64::consts::FRAC_PI_2))
+            .scale(0.5)
+            .translate(point(0.0, 4.0, 0.0));
+        assert_distance(&post, point(0.0, 6.0, 0.0), 0.5);
+        assert_distance(&post, point(1.0, 4.0, 0.0), 0.75);
+    }
+
+    #[test]
+    fn bounds_hold_the_whole_shape() {
+        let shapes: [Sdf; 7] = [
+            Sdf::new_round_box(&point(0.5, 0.0, 0.0), &point(1.0, 0.5, 1.5), 0.3),
+            Sdf::new_torus(&point(0.0, 1.0, 0.0), 2.0, 0.7),
+            Sdf::new_capsule(&point(-2.0, 0.0, 1.0), &point(1.0, 2.0, -1.0), 0.6),
+            // The blend swells past both spheres
+            Sdf::new_sphere(&point(-1.2, 0.0, 0.0), 1.0)
+                .smooth_union(Sdf::new_sphere(&point(1.2, 0.0, 0.0), 1.0), 1.5),
+            Sdf::new_sphere(&ORIGIN, 2.0)
+                .intersection(Sdf::new_box(&point(1.0, 0.0, 0.0), &point(1.5, 1.5, 1.5))),
+            Sdf::new_box(&ORIGIN, &point(2.0, 1.0, 1.0))
+                .difference(Sdf::new_sphere(&point(2.0, 0.0, 0.0), 1.0)),
+            Sdf::new_box(&ORIGIN, &point(2.0, 0.5, 0.5))
+                .rotate(point(0.3, 0.7, -0.4))
+                .scale(1.5)
+                .translate(point(0.5, -0.5, 0.0)),
+        ];
+        for shape in shapes {
+            let bounds: Aabb = shape.bounds().unwrap();
+            let mut inside: usize = 0;
+            for i in 0..=40 {
+                for j in 0..=40 {
+                    for k in 0..=40 {
+                        let p: Vec3 =
+                            point(i as f64, j as f64, k as f64) * 0.2 - point(4.0, 4.0, 4.0);
+                        if shape.distance(&p) > 0.0 {
+                            continue;
+                        }
+                        inside += 1;
+                        assert!(
+                            p.x >= bounds.min.x
+                                && p.y >= bounds.min.y
+                                && p.z >= bounds.min.z
+                                && p.x <= bounds.max.x
+                                && p.y <= bounds.max.y
+                                && p.z <= bounds.max.z,
+                            "{:?} is outside {:?} of {:?}",
+                            p,
+                            bounds,
+                            shape
+                        );
+                    }
+                }
+            }
+            assert!(inside > 0, "{:?} has nothing inside", shape);
+        }
+    }
+
+    #[test]
+    fn traced_hits_and_misses() {
+        // A unit ball 5 ahead
+        let ball = SdfObject::new(Sdf::new_sphere(&ORIGIN, 1.0)).move_to(point(0.0, 0.0, -5.0));
+        let r = Ray::new(ORIGIN, point(0.0, 0.0, -2.0));
+        let hit: Hit = ball.intersect(&r).unwrap();
+        assert!(fabs(hit.t - 2.0) < EPSILON);
+        assert!(hit.normal.approx_eq(&point(0.0, 0.0, 1.0), 1e-6));
+
+        // Off to the side, and pointing away
+        assert!(ball
+            .intersect(&Ray::new(ORIGIN, point(0.3, 0.0, -1.0)))
+            .is_none());
+        assert!(ball
+            .intersect(&Ray::new(ORIGIN, point(0.0, 0.0, 1.0)))
+            .is_none());
+
+        // A box turned on its side has its long face towards the ray
+        let slab = SdfObject::new(Sdf::new_box(&ORIGIN, &point(2.0, 0.5, 0.5)))
+            .rotate_around_center(point(0.0, std::f64::consts::FRAC_PI_2, 0.0))
+            .move_to(point(0.0, 0.0, -5.0));
+        let hit: Hit = slab
+            .intersect(&Ray::new(point(0.0, 0.0, 0.0), point(0.0, 0.0, -1.0)))
+            .unwrap();
+        assert!(fabs(hit.t - 3.0) < EPSILON);
+        assert!(hit.normal.approx_eq(&point(0.0, 0.0, 1.0), 1e-6));
+    }
+
+    #[test]
+    fn grazing_rays_are_more_occluded() {
+        let ball = SdfObject::new(Sdf::new_sphere(&ORIGIN, 1.0)).move_to(point(0.0, 0.0, -5.0));
+        let head_on: Hit = ball
+            .intersect(&Ray::new(ORIGIN, point(0.0, 0.0, -1.0)))
+            .unwrap();
+        // Just inside the rim, so it creeps along close to the surface
+        let grazing: Hit = ball
+            .intersect(&Ray::new(point(0.995, 0.0, 0.0), point(0.0, 0.0, -1.0)))
+            .unwrap();
+        assert!(head_on.occlusion < grazing.occlusion);
+        assert!(grazing.occlusion <= 1.0);
+    }
+}
@@ -1,3 +1,10 @@
+use libc::{
+    c_int, c_void, raise, sighandler_t, signal, tcgetattr, tcsetattr, termios, write, ECHO, ICANON,
+    SIGINT, SIGTERM, SIG_DFL, STDIN_FILENO, STDOUT_FILENO, TCSANOW, VMIN, VTIME,
+};
+use std::panic;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::OnceLock;
 use term_size;
 pub struct Terminal {
     columns: f64, // x-axis
@@ -26,3 +33,100 @@ impl Terminal {
         self.lines
     }
 }
+
+// Alternate screen, hidden cursor, every mouse motion (1003) in SGR encoding (1006)
+const ENTER_SEQUENCE: &[u8] = b"\x1B[?1049h\x1B[?25l\x1B[?1003h\x1B[?1006h";
+const LEAVE_SEQUENCE: &[u8] = b"\x1B[?1006l\x1B[?1003l\x1B[?25h\x1B[?1049l";
+
+// Kept in statics so the panic hook and signal handlers can reach them
+static ORIGINAL_MODE: OnceLock<termios> = OnceLock::new();
+static ACTIVE: AtomicBool = AtomicBool::new(false);
+
+// Owns the terminal while the renderer runs. The modes it sets are undone when it
+// is dropped, when the program panics and on SIGINT/SIGTERM.
+pub struct TerminalSession;
+
+impl TerminalSession {
+    pub fn new() -> TerminalSession {
+        let mut mode: termios = unsafe { std::mem::zeroed() };
+        if unsafe { tcgetattr(STDIN_FILENO, &mut mode) } == 0 {
+            let _ = ORIGINAL_MODE.set(mode);
+
+            // Non-canonical, no echo and non-blocking reads so keys arrive as they are pressed
+            mode.c_lflag &= !(ICANON | ECHO);
+            mode.c_cc[VMIN] = 0;
+            mode.c_cc[VTIME] = 0;
+            unsafe {
+                tcsetattr(STDIN_FILENO, TCSANOW, &mode);
+            }
+        }
+        write_all(ENTER_SEQUENCE);
+        ACTIVE.store(true, Ordering::SeqCst);
+
+        let previous_hook = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            restore();
+            previous_hook(info);
+        }));
+        unsafe {
+            signal(
+                SIGINT,
+                handle_signal as extern "C" fn(c_int) as sighandler_t,
+            );
+            signal(
+                SIGTERM,
+                handle_signal as extern "C" fn(c_int) as sighandler_t,
+            );
+        }
+        TerminalSession
+    }
+}
+
+impl Drop for TerminalSession {
+    fn drop(&mut self) {
+        restore();
+        unsafe {
+            signal(SIGINT, SIG_DFL);
+            signal(SIGTERM, SIG_DFL);
+        }
+    }
+}
+
+// Only async-signal-safe calls in here: it runs inside the signal handler
+fn restore() {
+    if !ACTIVE.swap(false, Ordering::SeqCst) {
+        return;
+    }
+    write_all(LEAVE_SEQUENCE);
+    if let Some(mode) = ORIGINAL_MODE.get() {
+        unsafe {
+            tcsetattr(STDIN_FILENO, TCSANOW, mode);
+        }
+    }
+}
+
+fn write_all(bytes: &[u8]) {
+    let mut written = 0;
+    while written < bytes.len() {
+        let n = unsafe {
+            write(
+                STDOUT_FILENO,
+                bytes[written..].as_ptr() as *const c_void,
+                bytes.len() - written,
+            )
+        };
+        if n <= 0 {
+            return;
+        }
+        written += n as usize;
+    }
+}
+
+extern "C" fn handle_signal(sig: c_int) {
+    restore();
+    // Die from the original signal so the shell sees the usual exit status
+    unsafe {
+        signal(sig, SIG_DFL);
+        raise(sig);
+    }
+}
@@ -0,0 +1,65 @@
+use crate::matrix::Mat4;
+use crate::rotation::Quaternion;
+use crate::vector::Vec3;
+
+// Scales, then rotates, then translates. Kept apart rather than baked into the
+// geometry, so animating one part never drifts the others.
+#[derive(Debug, Copy, Clone)]
+pub struct Transform {
+    pub translation: Vec3,
+    pub rotation: Quaternion,
+    pub scale: Vec3, // no component may be zero
+}
+
+impl Transform {
+    pub fn new(translation: Vec3, rotation: Quaternion, scale: Vec3) -> Transform {
+        Transform {
+            translation,
+            rotation,
+            scale,
+        }
+    }
+    pub fn identity() -> Transform {
+        Transform {
+            translation: Vec3 {
+                x: 0.0,
+                y: 0.0,
+                z: 0.0,
+            },
+            rotation: Quaternion::new(1.0, 0.0, 0.0, 0.0),
+            scale: Vec3 {
+                x: 1.0,
+                y: 1.0,
+                z: 1.0,
+            },
+        }
+    }
+
+    pub fn translate(&self, v: Vec3) -> Transform {
+        Transform {
+            translation: self.translation + v,
+            ..*self
+        }
+    }
+    // Turns about the transform's own origin
+    pub fn rotate(&self, euler: Vec3) -> Transform {
+        Transform {
+            rotation: (Quaternion::euler_to_quaternion(euler) * self.rotation).normalize(),
+            ..*self
+        }
+    }
+
+    pub fn matrix(&self) -> Mat4 {
+        Mat4::from_trs(&self.translation, &self.rotation, &self.scale)
+    }
+
+    // Undoes the translation, then the rotation, then the scale
+    pub fn inverse(&self) -> Mat4 {
+        Mat4::from_scale(&Vec3 {
+            x: 1.0 / self.scale.x,
+            y: 1.0 / self.scale.y,
+            z: 1.0 / self.scale.z,
+        }) * Mat4::from_quaternion(&self.rotation.inverse())
+            * Mat4::from_translation(&-self.translation)
+    }
+}
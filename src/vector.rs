@@ -1,6 +1,7 @@
 use crate::rotation;
+use libm::{fabs, sqrt};
 use std::ops;
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub struct Vec3 {
     pub x: f64,
     pub y: f64,
@@ -97,6 +98,56 @@ macro_rules! impl_vec3_operations {
             }
         }
 
+        // Vec3 / Vec3
+        impl ops::Div<$T> for $T {
+            type Output = Self;
+
+            fn div(self, rhs: Self) -> Self {
+                $T {
+                    x: self.x / rhs.x,
+                    y: self.y / rhs.y,
+                    z: self.z / rhs.z,
+                }
+            }
+        }
+
+        // -Vec3
+        impl ops::Neg for $T {
+            type Output = Self;
+
+            fn neg(self) -> Self {
+                $T {
+                    x: -self.x,
+                    y: -self.y,
+                    z: -self.z,
+                }
+            }
+        }
+
+        // Vec3[0] is x, Vec3[1] is y and Vec3[2] is z
+        impl ops::Index<usize> for $T {
+            type Output = f64;
+
+            fn index(&self, i: usize) -> &f64 {
+                match i {
+                    0 => &self.x,
+                    1 => &self.y,
+                    2 => &self.z,
+                    _ => panic!("Vec3 index {} out of range", i),
+                }
+            }
+        }
+        impl ops::IndexMut<usize> for $T {
+            fn index_mut(&mut self, i: usize) -> &mut f64 {
+                match i {
+                    0 => &mut self.x,
+                    1 => &mut self.y,
+                    2 => &mut self.z,
+                    _ => panic!("Vec3 index {} out of range", i),
+                }
+            }
+        }
+
         // Vec3 += Vec3
         impl ops::AddAssign<$T> for $T {
             fn add_assign(&mut self, rhs: Self) {
@@ -135,8 +186,174 @@ macro_rules! impl_vec3_operations {
                     z: u.x * v.y - u.y * v.x,
                 }
             }
+            pub fn distance(u: &$T, v: &$T) -> f64 {
+                (*u - *v).length()
+            }
+            pub fn lerp(u: &$T, v: &$T, t: f64) -> $T {
+                *u + (*v - *u) * t
+            }
+            pub fn min(u: &$T, v: &$T) -> $T {
+                $T {
+                    x: u.x.min(v.x),
+                    y: u.y.min(v.y),
+                    z: u.z.min(v.z),
+                }
+            }
+            pub fn max(u: &$T, v: &$T) -> $T {
+                $T {
+                    x: u.x.max(v.x),
+                    y: u.y.max(v.y),
+                    z: u.z.max(v.z),
+                }
+            }
+
+            pub fn length(&self) -> f64 {
+                sqrt($T::dot(self, self))
+            }
+            pub fn normalize(&self) -> $T {
+                *self / self.length()
+            }
+            pub fn abs(&self) -> $T {
+                $T {
+                    x: fabs(self.x),
+                    y: fabs(self.y),
+                    z: fabs(self.z),
+                }
+            }
+            // Bounces off a surface with unit normal `normal`
+            pub fn reflect(&self, normal: &$T) -> $T {
+                *self - *normal * (2.0 * $T::dot(self, normal))
+            }
+            // Bends a unit direction into a surface with unit normal `normal`
+            // facing it, `eta` being the ratio of refractive indices outside
+            // over inside. None on total internal reflection.
+            pub fn refract(&self, normal: &$T, eta: f64) -> Option<$T> {
+                let cos_i = -$T::dot(self, normal);
+                let k = 1.0 - eta * eta * (1.0 - cos_i * cos_i);
+                if k < 0.0 {
+                    return None;
+                }
+                Some(*self * eta + *normal * (eta * cos_i - sqrt(k)))
+            }
+            pub fn approx_eq(&self, other: &$T, tolerance: f64) -> bool {
+                fabs(self.x - other.x) <= tolerance
+                    && fabs(self.y - other.y) <= tolerance
+                    && fabs(self.z - other.z) <= tolerance
+            }
         }
     };
 }
 
 impl_vec3_operations!(Vec3);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn v(x: f64, y: f64, z: f64) -> Vec3 {
+        Vec3 { x, y, z }
+    }
+
+    #[test]
+    fn arithmetic_is_component_wise() {
+        let a: Vec3 = v(1.0, -2.0, 3.0);
+        let b: Vec3 = v(4.0, 0.5, -2.0);
+        assert_eq!(a + b, v(5.0, -1.5, 1.0));
+        assert_eq!(a - b, v(-3.0, -2.5, 5.0));
+        assert_eq!(a * b, v(4.0, -1.0, -6.0));
+        assert_eq!(a / b, v(0.25, -4.0, -1.5));
+        assert_eq!(-a, v(-1.0, 2.0, -3.0));
+    }
+
+    #[test]
+    fn scalars_scale_every_component() {
+        let a: Vec3 = v(1.0, -2.0, 3.0);
+        assert_eq!(a * 2.0, v(2.0, -4.0, 6.0));
+        assert_eq!(2.0 * a, a * 2.0);
+        assert_eq!(a / 2.0, v(0.5, -1.0, 1.5));
+    }
+
+    #[test]
+    fn assignments_match_their_operators() {
+        let a: Vec3 = v(1.0, -2.0, 3.0);
+        let b: Vec3 = v(4.0, 0.5, -2.0);
+        let mut c: Vec3 = a;
+        c += b;
+        assert_eq!(c, a + b);
+        c -= b;
+        assert_eq!(c, a);
+        c *= 3.0;
+        assert_eq!(c, a * 3.0);
+    }
+
+    #[test]
+    fn dot_and_cross() {
+        let x: Vec3 = v(1.0, 0.0, 0.0);
+        let y: Vec3 = v(0.0, 1.0, 0.0);
+        assert_eq!(Vec3::cross(&x, &y), v(0.0, 0.0, 1.0));
+        assert_eq!(Vec3::cross(&y, &x), v(0.0, 0.0, -1.0));
+        assert_eq!(Vec3::dot(&v(1.0, 2.0, 3.0), &v(4.0, -5.0, 6.0)), 12.0);
+    }
+
+    #[test]
+    fn indexing_reaches_each_component() {
+        let mut a: Vec3 = v(1.0, 2.0, 3.0);
+        assert_eq!([a[0], a[1], a[2]], [1.0, 2.0, 3.0]);
+        a[1] = -7.0;
+        assert_eq!(a, v(1.0, -7.0, 3.0));
+    }
+
+    #[test]
+    #[should_panic]
+    fn indexing_past_z_panics() {
+        let _ = v(1.0, 2.0, 3.0)[3];
+    }
+
+    #[test]
+    fn lengths_and_distances() {
+        assert_eq!(v(3.0, 4.0, 0.0).length(), 5.0);
+        assert_eq!(Vec3::distance(&v(1.0, 1.0, 1.0), &v(1.0, 4.0, 5.0)), 5.0);
+        assert!(v(0.0, 3.0, -4.0)
+            .normalize()
+            .approx_eq(&v(0.0, 0.6, -0.8), 1e-12));
+        assert!(!v(0.0, 0.6, -0.8).approx_eq(&v(0.0, 0.6, -0.7), 1e-12));
+    }
+
+    #[test]
+    fn component_wise_helpers() {
+        let a: Vec3 = v(1.0, -2.0, 3.0);
+        let b: Vec3 = v(4.0, 0.5, -2.0);
+        assert_eq!(Vec3::min(&a, &b), v(1.0, -2.0, -2.0));
+        assert_eq!(Vec3::max(&a, &b), v(4.0, 0.5, 3.0));
+        assert_eq!(a.abs(), v(1.0, 2.0, 3.0));
+        assert_eq!(Vec3::lerp(&a, &b, 0.0), a);
+        assert_eq!(Vec3::lerp(&a, &b, 1.0), b);
+        assert_eq!(Vec3::lerp(&a, &b, 0.5), v(2.5, -0.75, 0.5));
+    }
+
+    #[test]
+    fn reflect_flips_the_normal_part() {
+        let normal: Vec3 = v(0.0, 1.0, 0.0);
+        assert_eq!(v(1.0, -1.0, 0.0).reflect(&normal), v(1.0, 1.0, 0.0));
+    }
+
+    #[test]
+    fn refract_follows_snells_law() {
+        let normal: Vec3 = v(0.0, 1.0, 0.0);
+        let incoming: Vec3 = v(1.0, -1.0, 0.0).normalize();
+        // Straight through when nothing changes
+        let same: Vec3 = incoming.refract(&normal, 1.0).unwrap();
+        assert!(same.approx_eq(&incoming, 1e-12));
+
+        // Into glass, sin of the angles in the ratio of the indices
+        let eta: f64 = 1.0 / 1.5;
+        let bent: Vec3 = incoming.refract(&normal, eta).unwrap();
+        assert!((bent.length() - 1.0).abs() < 1e-12);
+        assert!((bent.x - incoming.x * eta).abs() < 1e-12);
+        assert!(bent.y < 0.0);
+
+        // Out of glass at a grazing angle it can't get out
+        let grazing: Vec3 = v(1.0, -0.1, 0.0).normalize();
+        assert!(grazing.refract(&normal, 1.5).is_none());
+    }
+}